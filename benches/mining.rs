@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+use blake2::{Blake2s, Digest};
+
+use blockchain_workshop::traits::Encode;
+use blockchain_workshop::types::{Block, HeaderHasher};
+use blockchain_workshop::utils::create_coinbase_tx;
+
+const ATTEMPTS: u128 = 20_000;
+
+fn block_with(transactions: usize) -> Block {
+    let mut block = Block::new(Some("00".repeat(32)));
+    for i in 0..transactions {
+        block.add_transaction(create_coinbase_tx(format!("account-{}", i), 1));
+    }
+    block
+}
+
+fn measure(mut attempt: impl FnMut(u128)) -> Duration {
+    let started = Instant::now();
+    for nonce in 0..ATTEMPTS {
+        attempt(nonce);
+    }
+    started.elapsed() / ATTEMPTS as u32
+}
+
+fn main() {
+    println!("{:>12} {:>16} {:>16} {:>8}", "transactions", "header", "midstate", "speedup");
+
+    // The merkle root is fixed while the nonce changes, so the block size
+    // shouldn't matter to either path.
+    for &transactions in &[1, 1_000] {
+        let block = block_with(transactions);
+
+        // Serializes and hashes the whole header per attempt, as mining did
+        // before the midstate. The nonce is encoded last.
+        let header = measure(|nonce| {
+            let mut bytes = block.header().to_bytes();
+            let nonce_at = bytes.len() - 16;
+            bytes[nonce_at..].copy_from_slice(&nonce.to_be_bytes());
+            assert!(!hex::encode(Blake2s::digest(&bytes)).is_empty());
+        });
+
+        let hasher = HeaderHasher::new(block.header());
+        let midstate = measure(|nonce| {
+            assert_ne!(hasher.hash(nonce), [0; 32]);
+        });
+
+        println!(
+            "{:>12} {:>16?} {:>16?} {:>7.1}x",
+            transactions,
+            header,
+            midstate,
+            header.as_secs_f64() / midstate.as_secs_f64()
+        );
+    }
+}
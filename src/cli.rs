@@ -0,0 +1,505 @@
+use std::convert::TryFrom;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+
+use crate::keystore::Keystore;
+use crate::miner::{Miner, MiningOutcome};
+use crate::rpc::RpcServer;
+use crate::traits::{Decode, Encode, Hashable, WorldState};
+use crate::types::{AccountId, Balance, Block, Blockchain, Error, SecretKeyBytes, Transaction, TransactionData};
+use crate::utils::{create_account_tx, create_transfer_tx, mint_initial_supply};
+
+const DEFAULT_DATA_DIR: &str = "data";
+const CHAIN_DIR: &str = "chain";
+const KEYS_DIR: &str = "keys";
+const MEMPOOL_FILE: &str = "mempool.dat";
+const DEFAULT_RPC_ADDR: &str = "127.0.0.1:7545";
+const PASSWORD_VAR: &str = "BLOCKCHAIN_WORKSHOP_PASSWORD";
+
+const USAGE: &str = "Usage: blockchain_workshop [--data-dir <dir>] [--password <password>] <command>
+
+Commands:
+    init <genesis-file>
+    account new <account-id>
+    account list
+    account import <account-id> <secret-key-hex>
+    account export <account-id>
+    account delete <account-id>
+    transfer <from> <to> <amount> [fee]
+    mine <count> <beneficiary>
+    show block <height|hash>
+    balance <account-id>
+    validate
+    serve [address]
+
+Without --password, the password is read from $BLOCKCHAIN_WORKSHOP_PASSWORD
+or from standard input. On a terminal the prompt doesn't echo the password;
+otherwise pipe it in rather than typing it.";
+
+enum Command {
+    Init(PathBuf),
+    NewAccount(AccountId),
+    ListAccounts,
+    ImportAccount(AccountId, SecretKeyBytes),
+    ExportAccount(AccountId),
+    DeleteAccount(AccountId),
+    Transfer(AccountId, AccountId, Balance, Balance),
+    Mine(usize, AccountId),
+    ShowBlock(String),
+    Balance(AccountId),
+    Validate,
+    Serve(SocketAddr),
+}
+
+pub fn run(args: &[String]) -> Result<String, Error> {
+    let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let mut data_dir = PathBuf::from(DEFAULT_DATA_DIR);
+    let mut password = None;
+    loop {
+        match args.as_slice() {
+            ["--data-dir", value, ..] => data_dir = PathBuf::from(value),
+            ["--password", value, ..] => password = Some(value.to_string()),
+            _ => break,
+        }
+        args.drain(..2);
+    }
+
+    let command = match args.as_slice() {
+        ["init", genesis] => Command::Init(PathBuf::from(genesis)),
+        ["account", "new", account_id] => Command::NewAccount(account_id.to_string()),
+        ["account", "list"] => Command::ListAccounts,
+        ["account", "import", account_id, secret] => {
+            let secret = hex::decode(secret)
+                .ok()
+                .and_then(|secret| SecretKeyBytes::try_from(secret.as_slice()).ok())
+                .ok_or("Invalid secret key.".to_string())?;
+            Command::ImportAccount(account_id.to_string(), secret)
+        }
+        ["account", "export", account_id] => Command::ExportAccount(account_id.to_string()),
+        ["account", "delete", account_id] => Command::DeleteAccount(account_id.to_string()),
+        ["transfer", from, to, amount] => Command::Transfer(from.to_string(), to.to_string(), parse(amount)?, 0),
+        ["transfer", from, to, amount, fee] => {
+            Command::Transfer(from.to_string(), to.to_string(), parse(amount)?, parse(fee)?)
+        }
+        ["mine", count, beneficiary] => Command::Mine(parse(count)?, beneficiary.to_string()),
+        ["show", "block", id] => Command::ShowBlock(id.to_string()),
+        ["balance", account_id] => Command::Balance(account_id.to_string()),
+        ["validate"] => Command::Validate,
+        ["serve"] => Command::Serve(parse_addr(DEFAULT_RPC_ADDR)?),
+        ["serve", addr] => Command::Serve(parse_addr(addr)?),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let mut node = Node::open(data_dir, password)?;
+    match command {
+        Command::Init(genesis) => node.init(&genesis),
+        Command::NewAccount(account_id) => node.new_account(&account_id),
+        Command::ListAccounts => node.list_accounts(),
+        Command::ImportAccount(account_id, secret) => node.import_account(&account_id, &secret),
+        Command::ExportAccount(account_id) => node.export_account(&account_id),
+        Command::DeleteAccount(account_id) => node.delete_account(&account_id),
+        Command::Transfer(from, to, amount, fee) => node.transfer(&from, &to, amount, fee),
+        Command::Mine(count, beneficiary) => node.mine(count, &beneficiary),
+        Command::ShowBlock(id) => node.show_block(&id),
+        Command::Balance(account_id) => node.balance(&account_id),
+        Command::Validate => node.validate(),
+        Command::Serve(addr) => node.serve(addr),
+    }
+}
+
+// Turns terminal echo off through stty while the password is typed. Where
+// stty isn't available the password is still read, just with echo on.
+fn read_password() -> io::Result<String> {
+    let terminal = io::stdin().is_terminal();
+    let set_echo = |on: bool| {
+        let _ = process::Command::new("stty")
+            .arg(if on { "echo" } else { "-echo" })
+            .stdin(process::Stdio::inherit())
+            .status();
+    };
+
+    let mut line = String::new();
+    if terminal {
+        eprint!("Password: ");
+        set_echo(false);
+    }
+    let result = io::stdin().lock().read_line(&mut line);
+    if terminal {
+        set_echo(true);
+        eprintln!();
+    }
+    result?;
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, Error> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number: {}", value))
+}
+
+fn parse_addr(value: &str) -> Result<SocketAddr, Error> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid address: {}", value))
+}
+
+fn parse_genesis(contents: &str) -> Result<Vec<(AccountId, Balance)>, Error> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [account_id, amount] => Ok((account_id.to_string(), parse(amount)?)),
+            _ => Err(format!("Invalid genesis entry: {}", line)),
+        })
+        .collect()
+}
+
+struct Node {
+    data_dir: PathBuf,
+    chain: Blockchain,
+    keystore: Keystore,
+    password: Option<String>,
+}
+
+impl Node {
+    fn open(data_dir: PathBuf, password: Option<String>) -> Result<Self, Error> {
+        let mut chain = Blockchain::open(&data_dir.join(CHAIN_DIR)).map_err(|e| e.to_string())?;
+
+        let mempool = data_dir.join(MEMPOOL_FILE);
+        if mempool.exists() {
+            let bytes = fs::read(&mempool).map_err(|e| e.to_string())?;
+            // Transactions invalidated by blocks mined since they were saved are dropped.
+            for tx in Vec::<Transaction>::from_bytes(&bytes)? {
+                let _ = chain.submit_transaction(tx);
+            }
+        }
+
+        let keystore = Keystore::open(&data_dir.join(KEYS_DIR)).map_err(|e| e.to_string())?;
+        Ok(Self {
+            data_dir,
+            chain,
+            keystore,
+            password,
+        })
+    }
+
+    fn save_mempool(&self) -> Result<(), Error> {
+        let pending: Vec<Transaction> = self.chain.transaction_pool().iter().cloned().collect();
+        fs::write(self.data_dir.join(MEMPOOL_FILE), pending.to_bytes()).map_err(|e| e.to_string())
+    }
+
+    fn password(&mut self) -> Result<String, Error> {
+        if self.password.is_none() {
+            let password = match env::var(PASSWORD_VAR) {
+                Ok(password) => password,
+                Err(_) => read_password().map_err(|e| e.to_string())?,
+            };
+            self.password = Some(password);
+        }
+        Ok(self.password.clone().unwrap_or_default())
+    }
+
+    fn init(&mut self, genesis: &Path) -> Result<String, Error> {
+        if !self.chain.is_empty() {
+            return Err("Chain is already initialized.".to_string());
+        }
+
+        let contents = fs::read_to_string(genesis).map_err(|e| e.to_string())?;
+        let allocations = parse_genesis(&contents)?;
+        if allocations.is_empty() {
+            return Err("Genesis file has no accounts.".to_string());
+        }
+
+        let password = self.password()?;
+        let mut block = Block::new(None);
+        for (account_id, amount) in &allocations {
+            let (keypair, tx) = create_account_tx(account_id.clone());
+            self.keystore
+                .insert(account_id, &keypair, &password)
+                .map_err(|e| e.to_string())?;
+            block.add_transaction(tx);
+            if *amount > 0 {
+                block.add_transaction(mint_initial_supply(account_id.clone(), *amount));
+            }
+        }
+        let state_root = self.chain.state_root_after(&block).map_err(|e| e.to_string())?;
+        block.set_state_root(state_root);
+        block.mine(self.chain.target()).map_err(|e| e.to_string())?;
+        let hash = block.hash();
+        self.chain.append_block(block).map_err(|e| e.to_string())?;
+
+        Ok(format!("Initialized chain with {} accounts, genesis {}", allocations.len(), hash))
+    }
+
+    fn new_account(&mut self, account_id: &str) -> Result<String, Error> {
+        let password = self.password()?;
+        let (keypair, tx) = create_account_tx(account_id.to_string());
+        self.chain.submit_transaction(tx).map_err(|e| e.to_string())?;
+        self.keystore
+            .insert(account_id, &keypair, &password)
+            .map_err(|e| e.to_string())?;
+        self.save_mempool()?;
+
+        Ok(format!("Account {} will be created in the next mined block.", account_id))
+    }
+
+    fn list_accounts(&self) -> Result<String, Error> {
+        let accounts = self.keystore.list().map_err(|e| e.to_string())?;
+        Ok(accounts.join("\n"))
+    }
+
+    fn import_account(&mut self, account_id: &str, secret: &SecretKeyBytes) -> Result<String, Error> {
+        let password = self.password()?;
+        let keypair = self
+            .keystore
+            .import(account_id, secret, &password)
+            .map_err(|e| e.to_string())?;
+        Ok(format!("Imported key {} for account {}", hex::encode(keypair.public.as_bytes()), account_id))
+    }
+
+    fn export_account(&mut self, account_id: &str) -> Result<String, Error> {
+        let password = self.password()?;
+        let secret = self.keystore.export(account_id, &password).map_err(|e| e.to_string())?;
+        Ok(hex::encode(secret))
+    }
+
+    fn delete_account(&mut self, account_id: &str) -> Result<String, Error> {
+        let password = self.password()?;
+        self.keystore.delete(account_id, &password).map_err(|e| e.to_string())?;
+        Ok(format!("Deleted key for account {}", account_id))
+    }
+
+    fn transfer(&mut self, from: &str, to: &str, amount: Balance, fee: Balance) -> Result<String, Error> {
+        let password = self.password()?;
+        let keypair = self.keystore.unlock(from, &password).map_err(|e| e.to_string())?;
+        let sender = self
+            .chain
+            .get_account_by_id(from.to_string())
+            .ok_or(format!("Unknown account {}.", from))?;
+        let nonce = sender.nonce() + self.chain.transaction_pool().pending_from(&from.to_string()).count() as u128;
+
+        let mut tx = create_transfer_tx(from.to_string(), to.to_string(), amount, fee, nonce);
+        tx.sign(&keypair);
+        let hash = tx.hash();
+        self.chain.submit_transaction(tx).map_err(|e| e.to_string())?;
+        self.save_mempool()?;
+
+        Ok(format!("Submitted transfer {}", hash))
+    }
+
+    fn mine(&mut self, count: usize, beneficiary: &str) -> Result<String, Error> {
+        if self.chain.is_empty() {
+            return Err("Chain is not initialized, run `init` first.".to_string());
+        }
+
+        let miner = Miner::default();
+        let mut out = String::new();
+        for _ in 0..count {
+            let template = self.chain.build_block_template(beneficiary.to_string()).map_err(|e| e.to_string())?;
+            let result = miner.start(template, self.chain.target(), None).wait().map_err(|e| e.to_string())?;
+            let block = match result.outcome {
+                MiningOutcome::Solved(block) => block,
+                outcome => return Err(format!("Mining stopped: {:?}", outcome)),
+            };
+            let hash = block.hash();
+            let transactions = block.transactions().len();
+            self.chain.append_block(block).map_err(|e| e.to_string())?;
+            writeln!(out, "Mined block {} {} ({} transactions)", self.chain.len() - 1, hash, transactions).unwrap();
+        }
+        self.save_mempool()?;
+
+        Ok(out.trim_end().to_string())
+    }
+
+    fn show_block(&self, id: &str) -> Result<String, Error> {
+        let blocks = self.chain.blocks();
+        let block = match id.parse::<usize>() {
+            Ok(height) if id.len() < 64 => blocks.get_by_height(height),
+            _ => blocks.get(&id.to_string()),
+        }
+        .ok_or(format!("Unknown block {}.", id))?;
+        let header = block.header();
+
+        let mut out = String::new();
+        writeln!(out, "hash:        {}", block.hash()).unwrap();
+        if let Some(height) = blocks.height(&block.hash()) {
+            writeln!(out, "height:      {}", height).unwrap();
+        }
+        writeln!(out, "prev_hash:   {}", header.prev_hash().map_or("-", String::as_str)).unwrap();
+        writeln!(out, "merkle_root: {}", header.merkle_root()).unwrap();
+        writeln!(out, "state_root:  {}", header.state_root()).unwrap();
+        writeln!(out, "timestamp:   {}", header.timestamp()).unwrap();
+        writeln!(out, "bits:        {:#010x}", header.bits()).unwrap();
+        writeln!(out, "nonce:       {}", header.nonce()).unwrap();
+        writeln!(out, "transactions:").unwrap();
+        for tx in block.transactions() {
+            writeln!(out, "    {} {}", tx.hash(), describe(tx)).unwrap();
+        }
+
+        Ok(out.trim_end().to_string())
+    }
+
+    fn balance(&self, account_id: &str) -> Result<String, Error> {
+        let account = self
+            .chain
+            .get_account_by_id(account_id.to_string())
+            .ok_or(format!("Unknown account {}.", account_id))?;
+
+        Ok(format!(
+            "balance: {}\nlocked:  {}\nnonce:   {}",
+            account.balance(),
+            account.locked_balance(),
+            account.nonce()
+        ))
+    }
+
+    fn validate(&self) -> Result<String, Error> {
+        self.chain.validate_full().map_err(|e| e.to_string())?;
+        Ok(format!("Chain is valid ({} blocks).", self.chain.len()))
+    }
+
+    fn serve(self, addr: SocketAddr) -> Result<String, Error> {
+        let server = RpcServer::start(Arc::new(Mutex::new(self.chain)), addr).map_err(|e| e.to_string())?;
+        eprintln!("Listening on http://{}", server.local_addr());
+        server.join();
+        Ok(String::new())
+    }
+}
+
+fn describe(tx: &Transaction) -> String {
+    match &tx.data {
+        TransactionData::CreateAccount(account_id, _) => format!("create account {}", account_id),
+        TransactionData::CreateMultisigAccount {
+            account_id,
+            public_keys,
+            threshold,
+        } => format!(
+            "create multisig account {} ({} of {})",
+            account_id,
+            threshold,
+            public_keys.len()
+        ),
+        TransactionData::MintInitialSupply { to, amount } => format!("mint {} to {}", amount, to),
+        TransactionData::Transfer { to, amount, fee } => format!(
+            "transfer {} from {} to {} (fee {})",
+            amount,
+            tx.from.as_deref().unwrap_or("-"),
+            to,
+            fee
+        ),
+        TransactionData::Coinbase { to, amount } => format!("coinbase {} to {}", amount, to),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::INITIAL_SUBSIDY;
+    use crate::utils::generate_account_id;
+
+    use super::*;
+
+    fn run_in(data_dir: &Path, args: &str) -> Result<String, Error> {
+        let mut full = vec![
+            "--data-dir".to_string(),
+            data_dir.to_str().unwrap().to_string(),
+            "--password".to_string(),
+            "secret".to_string(),
+        ];
+        full.extend(args.split_whitespace().map(str::to_string));
+        run(&full)
+    }
+
+    #[test]
+    fn test_commands() {
+        let data_dir = std::env::temp_dir().join(format!("blockchain_workshop_{}", generate_account_id()));
+        fs::create_dir_all(&data_dir).unwrap();
+        let genesis = data_dir.join("genesis.txt");
+        fs::write(&genesis, "# account balance\nsatoshi 1000\nalice 0\n").unwrap();
+
+        assert!(run_in(&data_dir, "mine 1 satoshi").is_err());
+        assert!(run_in(&data_dir, &format!("init {}", genesis.display())).is_ok());
+        assert!(run_in(&data_dir, &format!("init {}", genesis.display())).is_err());
+
+        assert!(run_in(&data_dir, "account new bob").is_ok());
+        assert!(run_in(&data_dir, "account new bob").is_err());
+        assert!(run_in(&data_dir, "transfer satoshi alice 100 1").is_ok());
+        assert!(run_in(&data_dir, "transfer satoshi alice 200").is_ok());
+        assert!(run_in(&data_dir, "transfer satoshi alice 1000").is_err());
+        assert!(run_in(&data_dir, "transfer bob satoshi 1").is_err());
+
+        let mined = run_in(&data_dir, "mine 2 alice").unwrap();
+        assert_eq!(mined.lines().count(), 2);
+
+        assert!(run_in(&data_dir, "balance satoshi").unwrap().starts_with("balance: 699\n"));
+        assert_eq!(
+            run_in(&data_dir, "balance alice"),
+            Ok(format!(
+                "balance: {}\nlocked:  {}\nnonce:   0",
+                300 + 2 * INITIAL_SUBSIDY + 1,
+                2 * INITIAL_SUBSIDY + 1
+            ))
+        );
+        assert!(run_in(&data_dir, "balance bob").unwrap().starts_with("balance: 0\n"));
+        assert!(run_in(&data_dir, "balance carol").is_err());
+
+        let block = run_in(&data_dir, "show block 1").unwrap();
+        assert!(block.contains("transfer 100 from satoshi to alice (fee 1)"));
+        assert!(block.contains("create account bob"));
+        let hash = block.lines().next().unwrap().trim_start_matches("hash:").trim().to_string();
+        assert_eq!(run_in(&data_dir, &format!("show block {}", hash)), Ok(block));
+        assert!(run_in(&data_dir, "show block 3").is_err());
+
+        assert_eq!(run_in(&data_dir, "validate"), Ok("Chain is valid (3 blocks).".to_string()));
+        assert_eq!(run_in(&data_dir, "frobnicate"), Err(USAGE.to_string()));
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_account_keys() {
+        let data_dir = std::env::temp_dir().join(format!("blockchain_workshop_{}", generate_account_id()));
+        let other_dir = std::env::temp_dir().join(format!("blockchain_workshop_{}", generate_account_id()));
+        let data = data_dir.to_str().unwrap();
+        fs::create_dir_all(&data_dir).unwrap();
+        let genesis = data_dir.join("genesis.txt");
+        fs::write(&genesis, "satoshi 1000\nalice 0\n").unwrap();
+        assert!(run_in(&data_dir, &format!("init {}", genesis.display())).is_ok());
+
+        assert_eq!(run_in(&data_dir, "account list"), Ok("alice\nsatoshi".to_string()));
+        let wrong_password = ["--data-dir", data, "--password", "guess", "transfer", "satoshi", "alice", "1"];
+        assert_eq!(
+            run(&wrong_password.iter().map(|arg| arg.to_string()).collect::<Vec<_>>()),
+            Err("Wrong password for account satoshi.".to_string())
+        );
+
+        let secret = run_in(&data_dir, "account export satoshi").unwrap();
+        assert!(run_in(&other_dir, &format!("account import satoshi {}", secret)).is_ok());
+        assert_eq!(run_in(&other_dir, "account export satoshi"), Ok(secret));
+        assert!(run_in(&other_dir, "account import alice 00").is_err());
+
+        assert!(run_in(&data_dir, "account delete satoshi").is_ok());
+        assert_eq!(run_in(&data_dir, "account list"), Ok("alice".to_string()));
+        assert!(run_in(&data_dir, "transfer satoshi alice 1").is_err());
+
+        fs::remove_dir_all(data_dir).unwrap();
+        fs::remove_dir_all(other_dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_genesis() {
+        assert_eq!(
+            parse_genesis("\n# comment\nsatoshi 10\n  alice 0  \n"),
+            Ok(vec![("satoshi".to_string(), 10), ("alice".to_string(), 0)])
+        );
+        assert!(parse_genesis("satoshi").is_err());
+        assert!(parse_genesis("satoshi ten").is_err());
+    }
+}
@@ -0,0 +1,305 @@
+use crate::traits::DifficultyAlgorithm;
+use crate::types::{
+    BlockHeader, Target, Timestamp, DIFFICULTY_ADJUSTMENT_INTERVAL, EXPECTED_TIME, MAX_TARGET,
+    U256,
+};
+
+#[derive(Debug, Clone)]
+pub struct EpochRetarget {
+    pub interval: usize,
+    pub target_spacing: Timestamp,
+    pub max_target: Target,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lwma {
+    pub window: usize,
+    pub target_spacing: Timestamp,
+    pub max_target: Target,
+}
+
+#[derive(Debug, Clone)]
+pub struct Asert {
+    pub half_life: Timestamp,
+    pub target_spacing: Timestamp,
+    pub max_target: Target,
+}
+
+impl Default for EpochRetarget {
+    fn default() -> Self {
+        Self {
+            interval: DIFFICULTY_ADJUSTMENT_INTERVAL,
+            target_spacing: EXPECTED_TIME as Timestamp,
+            max_target: U256::from_compact(MAX_TARGET).unwrap(),
+        }
+    }
+}
+
+impl Default for Lwma {
+    fn default() -> Self {
+        Self {
+            window: 45,
+            target_spacing: EXPECTED_TIME as Timestamp,
+            max_target: U256::from_compact(MAX_TARGET).unwrap(),
+        }
+    }
+}
+
+impl Default for Asert {
+    fn default() -> Self {
+        Self {
+            half_life: 288 * EXPECTED_TIME as Timestamp,
+            target_spacing: EXPECTED_TIME as Timestamp,
+            max_target: U256::from_compact(MAX_TARGET).unwrap(),
+        }
+    }
+}
+
+impl DifficultyAlgorithm for EpochRetarget {
+    fn max_target(&self) -> Target {
+        self.max_target
+    }
+
+    fn window(&self) -> usize {
+        self.interval + 1
+    }
+
+    fn next_target(&self, height: usize, _: &BlockHeader, headers: &[&BlockHeader]) -> Target {
+        let parent = match headers.last() {
+            Some(parent) if height > 1 => parent,
+            _ => return self.max_target,
+        };
+        let parent_target = header_target(parent, self.max_target);
+        if self.interval == 0 || !height.is_multiple_of(self.interval) || headers.len() < 2 {
+            return parent_target;
+        }
+
+        let expected = (headers.len() - 1) as Timestamp * self.target_spacing;
+        let actual = parent
+            .timestamp
+            .saturating_sub(headers[0].timestamp)
+            .clamp(expected / 4, expected * 4);
+        scale(parent_target, actual, expected, self.max_target)
+    }
+}
+
+impl DifficultyAlgorithm for Lwma {
+    fn max_target(&self) -> Target {
+        self.max_target
+    }
+
+    fn window(&self) -> usize {
+        self.window + 1
+    }
+
+    fn next_target(&self, height: usize, _: &BlockHeader, headers: &[&BlockHeader]) -> Target {
+        if height <= 1 || headers.len() < 2 {
+            return self.max_target;
+        }
+
+        let count = headers.len() - 1;
+        let mut previous = headers[0].timestamp;
+        let mut weighted_time: Timestamp = 0;
+        let mut total_target = U256::ZERO;
+        for (i, header) in headers[1..].iter().enumerate() {
+            let timestamp = header.timestamp.max(previous + 1);
+            let solve_time = (timestamp - previous).min(6 * self.target_spacing);
+            previous = timestamp;
+
+            weighted_time += (i as Timestamp + 1) * solve_time;
+            total_target = total_target.saturating_add(header_target(header, self.max_target));
+        }
+
+        let average = total_target
+            .checked_div(U256::from_u64(count as u64))
+            .unwrap();
+        let expected = (count * (count + 1) / 2) as Timestamp * self.target_spacing;
+        scale(average, weighted_time, expected, self.max_target)
+    }
+}
+
+impl DifficultyAlgorithm for Asert {
+    fn max_target(&self) -> Target {
+        self.max_target
+    }
+
+    fn window(&self) -> usize {
+        1
+    }
+
+    // Anchored at genesis with the max target, so block n is on schedule
+    // when its parent is target_spacing * (n - 1) seconds after genesis.
+    fn next_target(&self, height: usize, genesis: &BlockHeader, headers: &[&BlockHeader]) -> Target {
+        let parent = match headers.last() {
+            Some(parent) if height > 1 && self.half_life > 0 => parent,
+            _ => return self.max_target,
+        };
+
+        let time_delta = parent.timestamp as i128 - genesis.timestamp as i128;
+        let height_delta = (height - 1) as i128;
+        let exponent =
+            (time_delta - self.target_spacing as i128 * height_delta) * 65536 / self.half_life as i128;
+        let shifts = exponent >> 16;
+        let frac = (exponent & 0xffff) as u128;
+        let factor = 65536
+            + ((195_766_423_245_049 * frac
+                + 971_821_376 * frac.pow(2)
+                + 5_127 * frac.pow(3)
+                + (1 << 47))
+                >> 48);
+
+        let target = match (self.max_target >> 16).checked_mul_u64(factor as u64) {
+            Some(target) => target,
+            None => return self.max_target,
+        };
+        let target = if shifts >= 0 {
+            if target.bits() as i128 + shifts > 256 {
+                return self.max_target;
+            }
+            target << shifts as u32
+        } else if -shifts >= 256 {
+            U256::ZERO
+        } else {
+            target >> (-shifts) as u32
+        };
+
+        if target.is_zero() {
+            return U256::ONE;
+        }
+        target.min(self.max_target)
+    }
+}
+
+fn header_target(header: &BlockHeader, max_target: Target) -> Target {
+    header
+        .target()
+        .filter(|target| !target.is_zero())
+        .unwrap_or(max_target)
+        .min(max_target)
+}
+
+fn scale(target: Target, actual: Timestamp, expected: Timestamp, max_target: Target) -> Target {
+    let expected = U256::from_u64(expected.max(1));
+    let scaled = match target.checked_mul_u64(actual) {
+        Some(product) => product.checked_div(expected),
+        None => target
+            .checked_div(expected)
+            .and_then(|quotient| quotient.checked_mul_u64(actual)),
+    };
+    scaled.unwrap_or(max_target).min(max_target).max(U256::ONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(timestamps: &[Timestamp], bits: i32) -> Vec<BlockHeader> {
+        timestamps
+            .iter()
+            .map(|timestamp| BlockHeader {
+                timestamp: *timestamp,
+                bits,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn next_target(algorithm: &dyn DifficultyAlgorithm, chain: &[BlockHeader]) -> Target {
+        let height = chain.len();
+        let start = height.saturating_sub(algorithm.window());
+        let window: Vec<&BlockHeader> = chain[start..].iter().collect();
+        algorithm.next_target(height, &chain[0], &window)
+    }
+
+    fn schedule(count: usize, spacing: Timestamp) -> Vec<Timestamp> {
+        (0..count as Timestamp).map(|i| 1_000 + i * spacing).collect()
+    }
+
+    #[test]
+    fn test_epoch_retarget() {
+        let algorithm = EpochRetarget {
+            interval: 4,
+            target_spacing: 10,
+            max_target: U256::from_compact(MAX_TARGET).unwrap(),
+        };
+        let bits = 0x1d00ffff;
+        let target = U256::from_compact(bits).unwrap();
+
+        assert_eq!(next_target(&algorithm, &headers(&[1_000], 0)), algorithm.max_target);
+        assert_eq!(next_target(&algorithm, &headers(&schedule(3, 5), bits)), target);
+        assert_eq!(next_target(&algorithm, &headers(&schedule(4, 10), bits)), target);
+        assert_eq!(
+            next_target(&algorithm, &headers(&schedule(4, 5), bits)),
+            target >> 1
+        );
+        assert_eq!(
+            next_target(&algorithm, &headers(&schedule(8, 0), bits)),
+            target >> 2
+        );
+        assert_eq!(
+            next_target(&algorithm, &headers(&schedule(8, 1_000), bits)),
+            target << 2
+        );
+        assert_eq!(
+            next_target(&algorithm, &headers(&schedule(4, 1_000), MAX_TARGET)),
+            algorithm.max_target
+        );
+    }
+
+    #[test]
+    fn test_lwma() {
+        let algorithm = Lwma {
+            window: 5,
+            target_spacing: 10,
+            max_target: U256::from_compact(MAX_TARGET).unwrap(),
+        };
+        let bits = 0x1d00ffff;
+        let target = U256::from_compact(bits).unwrap();
+
+        assert_eq!(next_target(&algorithm, &headers(&[1_000], bits)), algorithm.max_target);
+        assert_eq!(next_target(&algorithm, &headers(&schedule(20, 10), bits)), target);
+        assert_eq!(next_target(&algorithm, &headers(&schedule(20, 5), bits)), target >> 1);
+        assert_eq!(
+            next_target(&algorithm, &headers(&schedule(20, 0), bits)),
+            target.checked_div(U256::from_u64(10)).unwrap()
+        );
+        assert_eq!(
+            next_target(&algorithm, &headers(&schedule(20, 1_000), bits)),
+            target.checked_mul_u64(6).unwrap()
+        );
+
+        let mut timestamps = schedule(20, 10);
+        timestamps[19] = timestamps[18] - 50;
+        assert_eq!(
+            next_target(&algorithm, &headers(&timestamps, bits)),
+            target
+                .checked_mul_u64(105)
+                .and_then(|target| target.checked_div(U256::from_u64(150)))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_asert() {
+        let algorithm = Asert {
+            half_life: 100,
+            target_spacing: 10,
+            max_target: U256::from_compact(MAX_TARGET).unwrap(),
+        };
+        let max_target = algorithm.max_target;
+
+        assert_eq!(next_target(&algorithm, &headers(&[1_000], 0)), max_target);
+        assert_eq!(next_target(&algorithm, &headers(&schedule(20, 10), 0)), max_target);
+        assert_eq!(next_target(&algorithm, &headers(&schedule(20, 1_000), 0)), max_target);
+
+        let mut timestamps = schedule(20, 10);
+        timestamps[19] -= 100;
+        assert_eq!(next_target(&algorithm, &headers(&timestamps, 0)), max_target >> 1);
+        timestamps[19] -= 100;
+        assert_eq!(next_target(&algorithm, &headers(&timestamps, 0)), max_target >> 2);
+
+        timestamps[19] += 150;
+        let ratio = next_target(&algorithm, &headers(&timestamps, 0)).as_f64() / max_target.as_f64();
+        assert!((ratio - 0.5f64.sqrt()).abs() < 1e-4);
+    }
+}
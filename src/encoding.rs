@@ -0,0 +1,201 @@
+use std::convert::TryFrom;
+
+use crate::traits::{Decode, Encode};
+use crate::types::Error;
+
+pub const ENCODING_VERSION: u8 = 1;
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() - self.position < len {
+            return Err("Unexpected end of input.".to_string());
+        }
+        let bytes = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    pub fn read_tag(&mut self) -> Result<u8, Error> {
+        u8::decode(self)
+    }
+}
+
+pub fn invalid_tag(tag: u8) -> Error {
+    format!("Invalid tag {}.", tag)
+}
+
+macro_rules! impl_int {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_be_bytes());
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(reader: &mut Reader) -> Result<Self, Error> {
+                    Ok(<$t>::from_be_bytes(reader.read_array()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_int!(u8, u32, u64, u128);
+
+impl Encode for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+}
+
+impl Decode for usize {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        usize::try_from(u64::decode(reader)?).map_err(|_| "Length overflow.".to_string())
+    }
+}
+
+impl Encode for i32 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl Decode for i32 {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(i32::from_be_bytes(reader.read_array()?))
+    }
+}
+
+impl<const N: usize> Encode for [u8; N] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+}
+
+impl<const N: usize> Decode for [u8; N] {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        reader.read_array()
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Decode for String {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        let len = u32::decode(reader)? as usize;
+        let bytes = reader.read_bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "Invalid UTF-8 string.".to_string())
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode(out);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        match reader.read_tag()? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(reader)?)),
+            tag => Err(invalid_tag(tag)),
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        for item in self {
+            item.encode(out);
+        }
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        let len = u32::decode(reader)?;
+        let mut items = Vec::new();
+        for _ in 0..len {
+            items.push(T::decode(reader)?);
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_primitives() {
+        let mut out = vec![];
+        7u32.encode(&mut out);
+        Some("ab".to_string()).encode(&mut out);
+        vec![1u8, 2].encode(&mut out);
+        assert_eq!(hex::encode(&out), "0000000701000000026162000000020102");
+
+        let mut reader = Reader::new(&out);
+        assert_eq!(u32::decode(&mut reader), Ok(7));
+        assert_eq!(Option::<String>::decode(&mut reader), Ok(Some("ab".to_string())));
+        assert_eq!(Vec::<u8>::decode(&mut reader), Ok(vec![1, 2]));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_input() {
+        assert_eq!(
+            u128::from_bytes(&[ENCODING_VERSION, 0, 1]),
+            Err("Unexpected end of input.".to_string())
+        );
+        assert_eq!(
+            u8::from_bytes(&[2, 0]),
+            Err("Unsupported encoding version 2.".to_string())
+        );
+        assert_eq!(
+            u8::from_bytes(&[ENCODING_VERSION, 0, 0]),
+            Err("Trailing bytes.".to_string())
+        );
+        assert_eq!(
+            Option::<u8>::from_bytes(&[ENCODING_VERSION, 2]),
+            Err("Invalid tag 2.".to_string())
+        );
+        assert_eq!(
+            String::from_bytes(&[ENCODING_VERSION, 0xff, 0xff, 0xff, 0xff]),
+            Err("Unexpected end of input.".to_string())
+        );
+    }
+}
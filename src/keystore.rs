@@ -0,0 +1,436 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+use rand::RngCore;
+
+use crate::encoding::Reader;
+use crate::traits::{Decode, Encode};
+use crate::types::{AccountId, Error, KeystoreError, PublicKeyBytes, SecretKeyBytes};
+
+const KEY_FILE_EXTENSION: &str = "key";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+// Upper bounds for parameters read back from key files, so a crafted file
+// can't make unlocking allocate or spin without limit.
+const MAX_MEMORY_COST: u32 = 1 << 21;
+const MAX_TIME_COST: u32 = 64;
+const MAX_PARALLELISM: u32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KdfParams {
+    pub memory_cost: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_cost: Params::DEFAULT_M_COST,
+            time_cost: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    fn derive_key(&self, password: &str, salt: &[u8]) -> Result<[u8; KEY_LEN], KeystoreError> {
+        if self.memory_cost > MAX_MEMORY_COST || self.time_cost > MAX_TIME_COST || self.parallelism > MAX_PARALLELISM {
+            return Err(KeystoreError::InvalidParams {
+                message: format!(
+                    "KDF parameters {}/{}/{} exceed the maximum {}/{}/{}.",
+                    self.memory_cost, self.time_cost, self.parallelism, MAX_MEMORY_COST, MAX_TIME_COST, MAX_PARALLELISM
+                ),
+            });
+        }
+
+        let invalid_params = |error: argon2::Error| KeystoreError::InvalidParams {
+            message: error.to_string(),
+        };
+        let params = Params::new(self.memory_cost, self.time_cost, self.parallelism, Some(KEY_LEN))
+            .map_err(invalid_params)?;
+
+        let mut key = [0; KEY_LEN];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), salt, &mut key)
+            .map_err(invalid_params)?;
+        Ok(key)
+    }
+}
+
+// The account id and public key are authenticated as associated data, so
+// editing either in the file makes unlocking fail.
+#[derive(Debug, Clone, PartialEq)]
+struct KeyFile {
+    account_id: AccountId,
+    public_key: PublicKeyBytes,
+    params: KdfParams,
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN],
+    ciphertext: Vec<u8>,
+}
+
+impl KeyFile {
+    fn associated_data(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.account_id.encode(&mut out);
+        self.public_key.encode(&mut out);
+        out
+    }
+}
+
+impl Encode for KeyFile {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.account_id.encode(out);
+        self.public_key.encode(out);
+        self.params.memory_cost.encode(out);
+        self.params.time_cost.encode(out);
+        self.params.parallelism.encode(out);
+        self.salt.encode(out);
+        self.nonce.encode(out);
+        self.ciphertext.encode(out);
+    }
+}
+
+impl Decode for KeyFile {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(Self {
+            account_id: AccountId::decode(reader)?,
+            public_key: PublicKeyBytes::decode(reader)?,
+            params: KdfParams {
+                memory_cost: u32::decode(reader)?,
+                time_cost: u32::decode(reader)?,
+                parallelism: u32::decode(reader)?,
+            },
+            salt: <[u8; SALT_LEN]>::decode(reader)?,
+            nonce: <[u8; NONCE_LEN]>::decode(reader)?,
+            ciphertext: Vec::<u8>::decode(reader)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Keystore {
+    path: PathBuf,
+    params: KdfParams,
+}
+
+impl Keystore {
+    pub fn open(path: &Path) -> Result<Self, KeystoreError> {
+        Self::open_with_params(path, KdfParams::default())
+    }
+
+    pub fn open_with_params(path: &Path, params: KdfParams) -> Result<Self, KeystoreError> {
+        fs::create_dir_all(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            params,
+        })
+    }
+
+    pub fn list(&self) -> Result<Vec<AccountId>, KeystoreError> {
+        let mut accounts = vec![];
+        for entry in fs::read_dir(&self.path)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some(KEY_FILE_EXTENSION) {
+                continue;
+            }
+            if let Some(account_id) = path.file_stem().and_then(|stem| stem.to_str()) {
+                accounts.push(account_id.to_string());
+            }
+        }
+        accounts.sort();
+        Ok(accounts)
+    }
+
+    pub fn contains(&self, account_id: &str) -> bool {
+        self.key_path(account_id).is_ok_and(|path| path.exists())
+    }
+
+    pub fn public_key(&self, account_id: &str) -> Result<PublicKeyBytes, KeystoreError> {
+        Ok(self.read(account_id)?.public_key)
+    }
+
+    pub fn insert(&self, account_id: &str, keypair: &Keypair, password: &str) -> Result<(), KeystoreError> {
+        let path = self.key_path(account_id)?;
+        if path.exists() {
+            return Err(KeystoreError::AccountExists {
+                account_id: account_id.to_string(),
+            });
+        }
+
+        let mut key_file = KeyFile {
+            account_id: account_id.to_string(),
+            public_key: keypair.public.to_bytes(),
+            params: self.params,
+            salt: [0; SALT_LEN],
+            nonce: [0; NONCE_LEN],
+            ciphertext: vec![],
+        };
+        let mut rng = rand::rngs::OsRng {};
+        rng.fill_bytes(&mut key_file.salt);
+        rng.fill_bytes(&mut key_file.nonce);
+
+        let key = key_file.params.derive_key(password, &key_file.salt)?;
+        let aad = key_file.associated_data();
+        key_file.ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(
+                Nonce::from_slice(&key_file.nonce),
+                Payload {
+                    msg: keypair.secret.as_bytes(),
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| KeystoreError::InvalidKey)?;
+
+        // Written under a temporary name first so a crash never leaves a torn key file.
+        let temp_path = path.with_extension("tmp");
+        write_private(&temp_path, &key_file.to_bytes())?;
+        fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+
+    pub fn import(&self, account_id: &str, secret: &SecretKeyBytes, password: &str) -> Result<Keypair, KeystoreError> {
+        let secret = SecretKey::from_bytes(secret).map_err(|_| KeystoreError::InvalidKey)?;
+        let public = PublicKey::from(&secret);
+        let keypair = Keypair { secret, public };
+        self.insert(account_id, &keypair, password)?;
+        Ok(keypair)
+    }
+
+    pub fn export(&self, account_id: &str, password: &str) -> Result<SecretKeyBytes, KeystoreError> {
+        Ok(self.unlock(account_id, password)?.secret.to_bytes())
+    }
+
+    pub fn unlock(&self, account_id: &str, password: &str) -> Result<Keypair, KeystoreError> {
+        let key_file = self.read(account_id)?;
+        let key = key_file.params.derive_key(password, &key_file.salt)?;
+        let aad = key_file.associated_data();
+        let secret = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(
+                Nonce::from_slice(&key_file.nonce),
+                Payload {
+                    msg: &key_file.ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| KeystoreError::WrongPassword {
+                account_id: account_id.to_string(),
+            })?;
+
+        let secret = SecretKey::from_bytes(&secret).map_err(|_| KeystoreError::InvalidKey)?;
+        let public = PublicKey::from(&secret);
+        if public.to_bytes() != key_file.public_key {
+            return Err(KeystoreError::InvalidKey);
+        }
+        Ok(Keypair { secret, public })
+    }
+
+    pub fn delete(&self, account_id: &str, password: &str) -> Result<(), KeystoreError> {
+        self.unlock(account_id, password)?;
+        fs::remove_file(self.key_path(account_id)?)?;
+        Ok(())
+    }
+
+    fn key_path(&self, account_id: &str) -> Result<PathBuf, KeystoreError> {
+        let valid = !account_id.is_empty()
+            && account_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(KeystoreError::InvalidAccountId {
+                account_id: account_id.to_string(),
+            });
+        }
+        Ok(self.path.join(format!("{}.{}", account_id, KEY_FILE_EXTENSION)))
+    }
+
+    fn read(&self, account_id: &str) -> Result<KeyFile, KeystoreError> {
+        let bytes = fs::read(self.key_path(account_id)?).map_err(|error| match error.kind() {
+            io::ErrorKind::NotFound => KeystoreError::UnknownAccount {
+                account_id: account_id.to_string(),
+            },
+            _ => error.into(),
+        })?;
+        let key_file = KeyFile::from_bytes(&bytes).map_err(|error| KeystoreError::Decode {
+            account_id: account_id.to_string(),
+            error,
+        })?;
+        if key_file.account_id != account_id {
+            return Err(KeystoreError::Decode {
+                account_id: account_id.to_string(),
+                error: format!("Key file belongs to account {}.", key_file.account_id),
+            });
+        }
+        Ok(key_file)
+    }
+}
+
+// Key files are only readable by their owner where the platform allows it.
+fn write_private(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let _ = fs::remove_file(path);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::{create_account_tx, create_transfer_tx, generate_account_id};
+
+    use super::*;
+
+    const TEST_PARAMS: KdfParams = KdfParams {
+        memory_cost: 64,
+        time_cost: 1,
+        parallelism: 1,
+    };
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("blockchain_workshop_{}", generate_account_id()))
+    }
+
+    fn keystore(path: &Path) -> Keystore {
+        Keystore::open_with_params(path, TEST_PARAMS).unwrap()
+    }
+
+    #[test]
+    fn test_insert_and_unlock() {
+        let path = temp_dir();
+        let keystore = keystore(&path);
+        let (keypair, tx_create) = create_account_tx("alice".to_string());
+        keystore.insert("alice", &keypair, "secret").unwrap();
+
+        assert_eq!(keystore.list(), Ok(vec!["alice".to_string()]));
+        assert!(keystore.contains("alice"));
+        assert_eq!(keystore.public_key("alice"), Ok(keypair.public.to_bytes()));
+        assert_eq!(
+            keystore.insert("alice", &keypair, "secret"),
+            Err(KeystoreError::AccountExists { account_id: "alice".to_string() })
+        );
+
+        let unlocked = keystore.unlock("alice", "secret").unwrap();
+        assert_eq!(unlocked.to_bytes(), keypair.to_bytes());
+
+        // The unlocked key signs transactions that verify against the created account.
+        let mut accounts = std::collections::HashMap::new();
+        tx_create.execute(&mut accounts, false).unwrap();
+        let mut tx = create_transfer_tx("alice".to_string(), "bob".to_string(), 1, 0, 0);
+        tx.sign(&unlocked);
+        assert!(tx.verify(&accounts["alice"]));
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        let path = temp_dir();
+        let keystore = keystore(&path);
+        let (keypair, _) = create_account_tx("alice".to_string());
+        keystore.insert("alice", &keypair, "secret").unwrap();
+
+        let wrong_password = Some(KeystoreError::WrongPassword { account_id: "alice".to_string() });
+        assert_eq!(keystore.unlock("alice", "guess").err(), wrong_password);
+        assert_eq!(keystore.export("alice", "guess").err(), wrong_password);
+        assert_eq!(keystore.delete("alice", "guess").err(), wrong_password);
+        assert!(keystore.contains("alice"));
+
+        assert_eq!(
+            keystore.unlock("bob", "secret").err(),
+            Some(KeystoreError::UnknownAccount { account_id: "bob".to_string() })
+        );
+        assert_eq!(
+            keystore.unlock("../alice", "secret").err(),
+            Some(KeystoreError::InvalidAccountId { account_id: "../alice".to_string() })
+        );
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_import_export_delete() {
+        let (source_path, destination_path) = (temp_dir(), temp_dir());
+        let source = keystore(&source_path);
+        let destination = keystore(&destination_path);
+        let (keypair, _) = create_account_tx("alice".to_string());
+        source.insert("alice", &keypair, "secret").unwrap();
+
+        let secret = source.export("alice", "secret").unwrap();
+        let imported = destination.import("alice", &secret, "other").unwrap();
+        assert_eq!(imported.to_bytes(), keypair.to_bytes());
+        assert_eq!(destination.unlock("alice", "other").unwrap().to_bytes(), keypair.to_bytes());
+
+        source.delete("alice", "secret").unwrap();
+        assert!(!source.contains("alice"));
+        assert_eq!(source.list(), Ok(vec![]));
+
+        fs::remove_dir_all(source_path).unwrap();
+        fs::remove_dir_all(destination_path).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_key_file() {
+        let path = temp_dir();
+        let keystore = keystore(&path);
+        let (alice, _) = create_account_tx("alice".to_string());
+        let (bob, _) = create_account_tx("bob".to_string());
+        keystore.insert("alice", &alice, "secret").unwrap();
+        keystore.insert("bob", &bob, "secret").unwrap();
+
+        // Swapping the public key breaks the authenticated associated data.
+        let key_path = keystore.key_path("alice").unwrap();
+        let mut key_file = KeyFile::from_bytes(&fs::read(&key_path).unwrap()).unwrap();
+        key_file.public_key = bob.public.to_bytes();
+        fs::write(&key_path, key_file.to_bytes()).unwrap();
+        assert_eq!(
+            keystore.unlock("alice", "secret").err(),
+            Some(KeystoreError::WrongPassword { account_id: "alice".to_string() })
+        );
+
+        fs::copy(keystore.key_path("bob").unwrap(), &key_path).unwrap();
+        assert!(matches!(
+            keystore.unlock("alice", "secret"),
+            Err(KeystoreError::Decode { .. })
+        ));
+
+        // Parameters are bounded before the KDF allocates anything.
+        let key_path = keystore.key_path("bob").unwrap();
+        let mut key_file = KeyFile::from_bytes(&fs::read(&key_path).unwrap()).unwrap();
+        key_file.params.memory_cost = u32::MAX;
+        fs::write(&key_path, key_file.to_bytes()).unwrap();
+        assert!(matches!(
+            keystore.unlock("bob", "secret"),
+            Err(KeystoreError::InvalidParams { .. })
+        ));
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_key_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_dir();
+        let keystore = keystore(&path);
+        let (alice, _) = create_account_tx("alice".to_string());
+        keystore.insert("alice", &alice, "secret").unwrap();
+        let mode = fs::metadata(keystore.key_path("alice").unwrap()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
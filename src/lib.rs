@@ -1,3 +1,14 @@
+pub mod cli;
+pub mod difficulty;
+pub mod encoding;
+pub mod hd;
+pub mod keystore;
+pub mod merkle;
+pub mod miner;
+pub mod network;
+pub mod rpc;
+pub mod state_tree;
+pub mod storage;
 pub mod traits;
 pub mod types;
 pub mod utils;
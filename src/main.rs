@@ -1,3 +1,14 @@
+use std::process;
+
+use blockchain_workshop::cli;
+
 fn main() {
-    dbg!(i32::MAX);
-}
\ No newline at end of file
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli::run(&args) {
+        Ok(output) => println!("{}", output),
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    }
+}
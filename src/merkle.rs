@@ -0,0 +1,197 @@
+use blake2::{Blake2s, Digest};
+use blake2::digest::FixedOutput;
+
+use crate::traits::Encode;
+use crate::types::{Hash, Transaction};
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub leaf_count: usize,
+    pub siblings: Vec<Hash>,
+}
+
+impl MerkleProof {
+    pub fn verify(&self, tx: &Transaction, root: &Hash) -> bool {
+        if self.index >= self.leaf_count {
+            return false;
+        }
+
+        let mut node = hash_leaf(&tx.to_bytes());
+        let mut siblings = self.siblings.iter();
+        let mut index = self.index;
+        let mut width = self.leaf_count;
+
+        while width > 1 {
+            let promoted = index == width - 1 && !width.is_multiple_of(2);
+            if !promoted {
+                let sibling = match siblings.next().map(hex::decode) {
+                    Some(Ok(sibling)) => sibling,
+                    _ => return false,
+                };
+                node = if index.is_multiple_of(2) {
+                    hash_node(&node, &sibling)
+                } else {
+                    hash_node(&sibling, &node)
+                };
+            }
+            index /= 2;
+            width = width.div_ceil(2);
+        }
+
+        siblings.next().is_none() && &hex::encode(node) == root
+    }
+}
+
+pub fn merkle_root(transactions: &[Transaction]) -> Hash {
+    let mut level = leaves(transactions);
+    if level.is_empty() {
+        return hex::encode(Blake2s::digest(&[]));
+    }
+
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    hex::encode(&level[0])
+}
+
+pub fn merkle_proof(transactions: &[Transaction], index: usize) -> Option<MerkleProof> {
+    if index >= transactions.len() {
+        return None;
+    }
+
+    let mut level = leaves(transactions);
+    let mut siblings = vec![];
+    let mut position = index;
+
+    while level.len() > 1 {
+        let sibling = if position.is_multiple_of(2) {
+            level.get(position + 1)
+        } else {
+            level.get(position - 1)
+        };
+        if let Some(sibling) = sibling {
+            siblings.push(hex::encode(sibling));
+        }
+        level = next_level(&level);
+        position /= 2;
+    }
+
+    Some(MerkleProof {
+        index,
+        leaf_count: transactions.len(),
+        siblings,
+    })
+}
+
+// Leaves cover the full encoding, signatures included, while Transaction::hash
+// stays a signature-free id. Otherwise a block would still verify after its
+// transactions were re-signed.
+fn leaves(transactions: &[Transaction]) -> Vec<Vec<u8>> {
+    transactions
+        .iter()
+        .map(|tx| hash_leaf(&tx.to_bytes()))
+        .collect()
+}
+
+fn next_level(level: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => hash_node(left, right),
+            [single] => single.clone(),
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+fn hash_leaf(data: &[u8]) -> Vec<u8> {
+    let mut hasher = Blake2s::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(data);
+    hasher.finalize_fixed().to_vec()
+}
+
+fn hash_node(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Blake2s::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize_fixed().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::{create_account_tx, generate_account_id};
+
+    use super::*;
+
+    fn transactions(count: usize) -> Vec<Transaction> {
+        (0..count)
+            .map(|_| create_account_tx(generate_account_id()).1)
+            .collect()
+    }
+
+    #[test]
+    fn test_proofs() {
+        for count in 1..=9 {
+            let txs = transactions(count);
+            let root = merkle_root(&txs);
+
+            for (index, tx) in txs.iter().enumerate() {
+                let proof = merkle_proof(&txs, index).unwrap();
+                assert!(proof.verify(tx, &root));
+
+                let other = &txs[(index + 1) % count];
+                if count > 1 {
+                    assert!(!proof.verify(other, &root));
+                }
+            }
+            assert!(merkle_proof(&txs, count).is_none());
+        }
+    }
+
+    #[test]
+    fn test_proof_tampering() {
+        let txs = transactions(5);
+        let root = merkle_root(&txs);
+        let proof = merkle_proof(&txs, 2).unwrap();
+
+        let mut wrong_index = proof.clone();
+        wrong_index.index = 3;
+        assert!(!wrong_index.verify(&txs[2], &root));
+
+        let mut extra_sibling = proof.clone();
+        extra_sibling.siblings.push(root.clone());
+        assert!(!extra_sibling.verify(&txs[2], &root));
+
+        let mut short = proof;
+        short.siblings.pop();
+        assert!(!short.verify(&txs[2], &root));
+    }
+
+    #[test]
+    fn test_domain_separation() {
+        let txs = transactions(2);
+        let left = hash_leaf(&txs[0].to_bytes());
+        let right = hash_leaf(&txs[1].to_bytes());
+
+        let node = hash_node(&left, &right);
+        assert_eq!(hex::encode(&node), merkle_root(&txs));
+        assert_ne!(node, hash_leaf(&[left, right].concat()));
+    }
+
+    #[test]
+    fn test_commits_to_signatures() {
+        let mut txs = transactions(3);
+        let root = merkle_root(&txs);
+        let proof = merkle_proof(&txs, 1).unwrap();
+
+        txs[1].set_sign([9; 64]);
+        assert_ne!(merkle_root(&txs), root);
+        assert!(!proof.verify(&txs[1], &root));
+    }
+}
@@ -0,0 +1,340 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvError, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::types::{Block, BlockHeader, HeaderHasher, MAX_NONCE, SharedChain, Target};
+
+const BATCH_SIZE: u64 = 1024;
+const TIP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MiningOutcome {
+    Solved(Block),
+    Cancelled,
+    DeadlineExpired,
+    Exhausted,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MiningResult {
+    pub outcome: MiningOutcome,
+    pub attempts: u64,
+    pub elapsed: Duration,
+}
+
+impl MiningResult {
+    pub fn hashrate(&self) -> f64 {
+        hashrate(self.attempts, self.elapsed)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+pub struct MiningHandle {
+    cancel: CancelToken,
+    finished: CancelToken,
+    attempts: Arc<AtomicU64>,
+    started: Instant,
+    results: Receiver<MiningResult>,
+}
+
+impl MiningHandle {
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    pub fn attempts(&self) -> u64 {
+        self.attempts.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn hashrate(&self) -> f64 {
+        hashrate(self.attempts(), self.elapsed())
+    }
+
+    pub fn results(&self) -> &Receiver<MiningResult> {
+        &self.results
+    }
+
+    pub fn try_result(&self) -> Result<MiningResult, TryRecvError> {
+        self.results.try_recv()
+    }
+
+    pub fn wait(self) -> Result<MiningResult, RecvError> {
+        self.results.recv()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Miner {
+    threads: usize,
+}
+
+impl Default for Miner {
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        Self::new(threads)
+    }
+}
+
+impl Miner {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads: threads.max(1),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn start(&self, mut block: Block, target: Target, deadline: Option<Instant>) -> MiningHandle {
+        block.set_bits(target.to_compact());
+        let target = block.header().target().unwrap_or(target);
+
+        let cancel = CancelToken::default();
+        let finished = CancelToken::default();
+        let attempts = Arc::new(AtomicU64::new(0));
+        let started = Instant::now();
+        let (results_sender, results) = mpsc::channel();
+        let (found_sender, found) = mpsc::channel();
+
+        // Each worker owns the full nonce range of its own extra nonces.
+        let threads = self.threads as u64;
+        let workers: Vec<_> = (0..threads)
+            .map(|i| {
+                let mut header = block.header().clone();
+                header.extra_nonce = header.extra_nonce.wrapping_add(i);
+                let cancel = cancel.clone();
+                let attempts = attempts.clone();
+                let found_sender = found_sender.clone();
+                thread::spawn(move || {
+                    let solved = search(header, threads, target, &cancel, &attempts, deadline);
+                    let _ = found_sender.send(solved);
+                })
+            })
+            .collect();
+        drop(found_sender);
+
+        let coordinator_cancel = cancel.clone();
+        let coordinator_finished = finished.clone();
+        let coordinator_attempts = attempts.clone();
+        thread::spawn(move || {
+            let mut solved = None;
+            for header in found.iter().flatten() {
+                coordinator_cancel.cancel();
+                solved.get_or_insert(header);
+            }
+            for worker in workers {
+                let _ = worker.join();
+            }
+
+            let outcome = match solved {
+                Some(header) => {
+                    block.header.extra_nonce = header.extra_nonce;
+                    block.set_nonce(header.nonce);
+                    MiningOutcome::Solved(block)
+                }
+                None if coordinator_cancel.is_cancelled() => MiningOutcome::Cancelled,
+                None if deadline.is_some() => MiningOutcome::DeadlineExpired,
+                None => MiningOutcome::Exhausted,
+            };
+            coordinator_finished.cancel();
+            let _ = results_sender.send(MiningResult {
+                outcome,
+                attempts: coordinator_attempts.load(Ordering::Relaxed),
+                elapsed: started.elapsed(),
+            });
+        });
+
+        MiningHandle {
+            cancel,
+            finished,
+            attempts,
+            started,
+            results,
+        }
+    }
+}
+
+impl Miner {
+    // Like `start`, but gives up with `Cancelled` as soon as the chain's tip
+    // moves away from the block's parent, since the work would be stale.
+    pub fn start_on_tip(&self, chain: SharedChain, block: Block, target: Target, deadline: Option<Instant>) -> MiningHandle {
+        let prev_hash = block.prev_hash().cloned();
+        let handle = self.start(block, target, deadline);
+        let cancel = handle.cancel.clone();
+        let finished = handle.finished.clone();
+        thread::spawn(move || {
+            while !finished.is_cancelled() && !cancel.is_cancelled() {
+                if chain.lock().unwrap().get_last_block_hash() != prev_hash {
+                    cancel.cancel();
+                    break;
+                }
+                thread::sleep(TIP_POLL_INTERVAL);
+            }
+        });
+        handle
+    }
+}
+
+fn search(
+    mut header: BlockHeader,
+    stride: u64,
+    target: Target,
+    cancel: &CancelToken,
+    attempts: &AtomicU64,
+    deadline: Option<Instant>,
+) -> Option<BlockHeader> {
+    let mut batch = 0;
+    loop {
+        let hasher = HeaderHasher::new(&header);
+        for nonce in 0..=MAX_NONCE {
+            batch += 1;
+            if hasher.meets_target(nonce, &target) {
+                attempts.fetch_add(batch, Ordering::Relaxed);
+                header.nonce = nonce;
+                return Some(header);
+            }
+
+            if batch == BATCH_SIZE {
+                attempts.fetch_add(batch, Ordering::Relaxed);
+                batch = 0;
+                if cancel.is_cancelled() || matches!(deadline, Some(deadline) if Instant::now() >= deadline) {
+                    return None;
+                }
+            }
+        }
+
+        match header.extra_nonce.checked_add(stride) {
+            Some(extra_nonce) => header.extra_nonce = extra_nonce,
+            _ => break,
+        }
+    }
+    attempts.fetch_add(batch, Ordering::Relaxed);
+    None
+}
+
+fn hashrate(attempts: u64, elapsed: Duration) -> f64 {
+    match elapsed.as_secs_f64() {
+        secs if secs > 0. => attempts as f64 / secs,
+        _ => 0.,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::types::{Blockchain, U256};
+    use crate::utils::{append_block, create_account_tx, generate_account_id, new_block};
+
+    use super::*;
+
+    fn next_block(bc: &mut Blockchain) -> Block {
+        let mut block = new_block(bc);
+        block.add_transaction(create_account_tx(generate_account_id()).1);
+        block.set_state_root(bc.state_root_after(&block).unwrap());
+        block
+    }
+
+    #[test]
+    fn test_solve() {
+        let bc = &mut Blockchain::new();
+        append_block(bc);
+
+        let handle = Miner::new(4).start(next_block(bc), bc.target(), None);
+        let result = handle.wait().unwrap();
+        assert!(result.attempts > 0);
+        assert!(result.hashrate() > 0.);
+
+        match result.outcome {
+            MiningOutcome::Solved(block) => {
+                assert!(block.verify());
+                assert!(block.header().meets_target());
+                assert!(bc.append_block(block).is_ok());
+            }
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_cancel() {
+        let bc = &mut Blockchain::new();
+        append_block(bc);
+
+        let handle = Miner::new(2).start(next_block(bc), U256::ZERO, None);
+        thread::sleep(Duration::from_millis(50));
+        assert!(handle.try_result().is_err());
+        assert!(handle.attempts() > 0);
+
+        handle.cancel_token().cancel();
+        let result = handle.wait().unwrap();
+        assert_eq!(result.outcome, MiningOutcome::Cancelled);
+        assert!(result.attempts > 0);
+    }
+
+    #[test]
+    fn test_cancel_on_new_tip() {
+        let chain = Arc::new(Mutex::new(Blockchain::new()));
+        append_block(&mut chain.lock().unwrap());
+
+        let block = next_block(&mut chain.lock().unwrap());
+        let handle = Miner::new(2).start_on_tip(chain.clone(), block, U256::ZERO, None);
+        thread::sleep(Duration::from_millis(100));
+        assert!(handle.try_result().is_err());
+
+        append_block(&mut chain.lock().unwrap());
+        let result = handle.wait().unwrap();
+        assert_eq!(result.outcome, MiningOutcome::Cancelled);
+    }
+
+    #[test]
+    fn test_start_on_tip() {
+        let chain = Arc::new(Mutex::new(Blockchain::new()));
+        append_block(&mut chain.lock().unwrap());
+
+        let (block, target) = {
+            let bc = &mut chain.lock().unwrap();
+            (next_block(bc), bc.target())
+        };
+        let result = Miner::new(2).start_on_tip(chain.clone(), block, target, None).wait().unwrap();
+        match result.outcome {
+            MiningOutcome::Solved(block) => assert!(chain.lock().unwrap().append_block(block).is_ok()),
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_deadline() {
+        let bc = &mut Blockchain::new();
+        append_block(bc);
+
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let handle = Miner::new(2).start(next_block(bc), U256::ZERO, Some(deadline));
+        let result = handle.wait().unwrap();
+        assert_eq!(result.outcome, MiningOutcome::DeadlineExpired);
+        assert!(result.elapsed >= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,520 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::traits::{Decode, Encode, Hashable};
+use crate::types::{Block, BlockError, Blockchain, BlockHeader, ChainUpdate, Error, Hash, Message, PoolError, SharedChain, Transaction};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const MAX_PEERS: usize = 32;
+const MAX_MESSAGE_LEN: usize = 32 << 20;
+const MAX_HEADERS: usize = 2_000;
+const MAX_QUEUED_MESSAGES: usize = 1_024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+struct Peer {
+    addr: SocketAddr,
+    stream: TcpStream,
+    sender: SyncSender<Message>,
+    height: Option<u64>,
+    acked: bool,
+}
+
+impl Peer {
+    fn is_ready(&self) -> bool {
+        self.height.is_some() && self.acked
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    chain: SharedChain,
+    peers: Mutex<HashMap<usize, Peer>>,
+    next_peer_id: AtomicUsize,
+    shutdown: AtomicBool,
+}
+
+#[derive(Debug)]
+pub struct Node {
+    shared: Arc<Shared>,
+    local_addr: SocketAddr,
+    listener: JoinHandle<()>,
+}
+
+impl Node {
+    pub fn start(chain: SharedChain, addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let shared = Arc::new(Shared {
+            chain,
+            peers: Mutex::new(HashMap::new()),
+            next_peer_id: AtomicUsize::new(0),
+            shutdown: AtomicBool::new(false),
+        });
+
+        let listener_shared = shared.clone();
+        let listener = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if listener_shared.shutdown.load(Ordering::Relaxed) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let _ = add_peer(&listener_shared, stream);
+                }
+            }
+        });
+
+        Ok(Self {
+            shared,
+            local_addr,
+            listener,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn chain(&self) -> &SharedChain {
+        &self.shared.chain
+    }
+
+    pub fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+        let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        add_peer(&self.shared, stream)
+    }
+
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        let peers = self.shared.peers.lock().unwrap();
+        peers.values().filter(|peer| peer.is_ready()).map(|peer| peer.addr).collect()
+    }
+
+    pub fn submit_block(&self, block: Block) -> Result<ChainUpdate, BlockError> {
+        let hash = block.hash();
+        let update = self.shared.chain.lock().unwrap().append_block(block)?;
+        if !matches!(update, ChainUpdate::SideBranch) {
+            relay(&self.shared, None, announce_block(hash));
+        }
+        Ok(update)
+    }
+
+    pub fn submit_transaction(&self, tx: Transaction) -> Result<(), PoolError> {
+        let hash = tx.hash();
+        self.shared.chain.lock().unwrap().submit_transaction(tx)?;
+        relay(&self.shared, None, announce_transaction(hash));
+        Ok(())
+    }
+
+    pub fn stop(self) {
+        self.shared.shutdown.store(true, Ordering::Relaxed);
+        // Wakes up the blocking accept so the listener sees the flag.
+        let _ = TcpStream::connect(self.local_addr);
+        let _ = self.listener.join();
+        for peer in self.shared.peers.lock().unwrap().values() {
+            let _ = peer.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+fn add_peer(shared: &Arc<Shared>, stream: TcpStream) -> io::Result<()> {
+    if shared.peers.lock().unwrap().len() >= MAX_PEERS {
+        let _ = stream.shutdown(Shutdown::Both);
+        return Err(io::Error::new(io::ErrorKind::ConnectionRefused, "Too many peers."));
+    }
+
+    let id = shared.next_peer_id.fetch_add(1, Ordering::Relaxed);
+    let addr = stream.peer_addr()?;
+    let mut reader = stream.try_clone()?;
+    let mut writer = stream.try_clone()?;
+
+    // Each peer gets its own writer thread so a slow peer never blocks
+    // the threads handling other peers. A peer that lets its queue fill up
+    // is disconnected rather than buffered for without bound.
+    let (sender, outgoing) = mpsc::sync_channel::<Message>(MAX_QUEUED_MESSAGES);
+    thread::spawn(move || {
+        for message in outgoing {
+            if write_message(&mut writer, &message).is_err() {
+                break;
+            }
+        }
+        let _ = writer.shutdown(Shutdown::Both);
+    });
+
+    let version = version_message(&shared.chain.lock().unwrap());
+    let _ = sender.send(version);
+    shared.peers.lock().unwrap().insert(
+        id,
+        Peer {
+            addr,
+            stream,
+            sender,
+            height: None,
+            acked: false,
+        },
+    );
+
+    let watchdog = shared.clone();
+    thread::spawn(move || {
+        thread::sleep(HANDSHAKE_TIMEOUT);
+        if let Some(peer) = watchdog.peers.lock().unwrap().get(&id) {
+            if !peer.is_ready() {
+                let _ = peer.stream.shutdown(Shutdown::Both);
+            }
+        }
+    });
+
+    let shared = shared.clone();
+    thread::spawn(move || {
+        while let Ok(message) = read_message(&mut reader) {
+            if handle_message(&shared, id, message).is_err() {
+                break;
+            }
+        }
+        if let Some(peer) = shared.peers.lock().unwrap().remove(&id) {
+            let _ = peer.stream.shutdown(Shutdown::Both);
+        }
+    });
+    Ok(())
+}
+
+fn handle_message(shared: &Shared, id: usize, message: Message) -> Result<(), Error> {
+    match message {
+        Message::Version { version, genesis, height } => {
+            if version != PROTOCOL_VERSION {
+                return Err(format!("Unsupported protocol version {}.", version));
+            }
+            let ours = genesis_hash(&shared.chain.lock().unwrap());
+            if matches!((&ours, &genesis), (Some(ours), Some(theirs)) if ours != theirs) {
+                return Err("Peer is on a different genesis block.".to_string());
+            }
+
+            with_peer(shared, id, |peer| match peer.height {
+                Some(_) => Err("Duplicate version message.".to_string()),
+                None => {
+                    peer.height = Some(height);
+                    Ok(())
+                }
+            })?;
+            send(shared, id, Message::VerAck);
+            start_sync(shared, id);
+            Ok(())
+        }
+        Message::VerAck => {
+            with_peer(shared, id, |peer| {
+                peer.acked = true;
+                Ok(())
+            })?;
+            start_sync(shared, id);
+            Ok(())
+        }
+        message => {
+            if !with_peer(shared, id, |peer| Ok(peer.is_ready()))? {
+                return Err("Message before handshake.".to_string());
+            }
+            handle_ready_message(shared, id, message)
+        }
+    }
+}
+
+fn handle_ready_message(shared: &Shared, id: usize, message: Message) -> Result<(), Error> {
+    match message {
+        Message::GetHeaders { locator } => {
+            let headers = headers_after(&shared.chain.lock().unwrap(), &locator);
+            send(shared, id, Message::Headers(headers));
+        }
+        Message::Headers(headers) => {
+            let missing = missing_blocks(&shared.chain.lock().unwrap(), &headers)?;
+            if !missing.is_empty() {
+                send(
+                    shared,
+                    id,
+                    Message::GetData {
+                        blocks: missing,
+                        transactions: vec![],
+                    },
+                );
+            }
+            if headers.len() == MAX_HEADERS {
+                let locator = headers.last().map(|header| vec![header.hash()]).unwrap_or_default();
+                send(shared, id, Message::GetHeaders { locator });
+            }
+        }
+        Message::Inventory { blocks, transactions } => {
+            let (unknown_blocks, unknown_transactions) = {
+                let chain = shared.chain.lock().unwrap();
+                let unknown_blocks = blocks.iter().any(|hash| !chain.blocks().contains(hash));
+                let unknown_transactions: Vec<Hash> = transactions
+                    .into_iter()
+                    .filter(|hash| !chain.transaction_pool().contains(hash))
+                    .collect();
+                (unknown_blocks, unknown_transactions)
+            };
+            // Announced blocks are fetched through headers so that any missing
+            // ancestors are requested along with them.
+            if unknown_blocks {
+                let locator = locator(&shared.chain.lock().unwrap());
+                send(shared, id, Message::GetHeaders { locator });
+            }
+            if !unknown_transactions.is_empty() {
+                send(
+                    shared,
+                    id,
+                    Message::GetData {
+                        blocks: vec![],
+                        transactions: unknown_transactions,
+                    },
+                );
+            }
+        }
+        Message::GetData { blocks, transactions } => {
+            let mut replies = vec![];
+            {
+                let chain = shared.chain.lock().unwrap();
+                for hash in &blocks {
+                    if let Some(block) = chain.blocks().get(hash) {
+                        replies.push(Message::Block(block.clone()));
+                    }
+                }
+                for hash in &transactions {
+                    if let Some(tx) = chain.transaction_pool().get(hash) {
+                        replies.push(Message::Transaction(tx.clone()));
+                    }
+                }
+            }
+            for reply in replies {
+                send(shared, id, reply);
+            }
+        }
+        Message::Block(block) => {
+            let hash = block.hash();
+            let result = shared.chain.lock().unwrap().append_block(block);
+            match result {
+                Ok(ChainUpdate::SideBranch) | Err(BlockError::AlreadyKnown) => {}
+                Ok(_) => relay(shared, Some(id), announce_block(hash)),
+                Err(BlockError::UnknownParent) => {
+                    let locator = locator(&shared.chain.lock().unwrap());
+                    send(shared, id, Message::GetHeaders { locator });
+                }
+                Err(error) => return Err(error.to_string()),
+            }
+        }
+        Message::Transaction(tx) => {
+            let hash = tx.hash();
+            if shared.chain.lock().unwrap().submit_transaction(tx).is_ok() {
+                relay(shared, Some(id), announce_transaction(hash));
+            }
+        }
+        Message::Version { .. } | Message::VerAck => {}
+    }
+    Ok(())
+}
+
+fn start_sync(shared: &Shared, id: usize) {
+    let peer_height = match with_peer(shared, id, |peer| Ok(peer.height.filter(|_| peer.is_ready()))) {
+        Ok(Some(height)) => height,
+        _ => return,
+    };
+    let locator = {
+        let chain = shared.chain.lock().unwrap();
+        if peer_height <= chain.len() as u64 {
+            return;
+        }
+        locator(&chain)
+    };
+    send(shared, id, Message::GetHeaders { locator });
+}
+
+fn with_peer<T>(shared: &Shared, id: usize, f: impl FnOnce(&mut Peer) -> Result<T, Error>) -> Result<T, Error> {
+    match shared.peers.lock().unwrap().get_mut(&id) {
+        Some(peer) => f(peer),
+        None => Err("Peer disconnected.".to_string()),
+    }
+}
+
+fn send(shared: &Shared, id: usize, message: Message) {
+    if let Some(peer) = shared.peers.lock().unwrap().get(&id) {
+        queue(peer, message);
+    }
+}
+
+fn relay(shared: &Shared, source: Option<usize>, message: Message) {
+    for (id, peer) in shared.peers.lock().unwrap().iter() {
+        if Some(*id) != source && peer.is_ready() {
+            queue(peer, message.clone());
+        }
+    }
+}
+
+fn queue(peer: &Peer, message: Message) {
+    if let Err(TrySendError::Full(_)) = peer.sender.try_send(message) {
+        let _ = peer.stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn announce_block(hash: Hash) -> Message {
+    Message::Inventory {
+        blocks: vec![hash],
+        transactions: vec![],
+    }
+}
+
+fn announce_transaction(hash: Hash) -> Message {
+    Message::Inventory {
+        blocks: vec![],
+        transactions: vec![hash],
+    }
+}
+
+fn version_message(chain: &Blockchain) -> Message {
+    Message::Version {
+        version: PROTOCOL_VERSION,
+        genesis: genesis_hash(chain),
+        height: chain.len() as u64,
+    }
+}
+
+fn genesis_hash(chain: &Blockchain) -> Option<Hash> {
+    chain.blocks().get_by_height(0).map(|block| block.hash())
+}
+
+// Hashes of the active chain from the tip back to genesis, dense near the
+// tip and exponentially sparser further back.
+fn locator(chain: &Blockchain) -> Vec<Hash> {
+    let mut locator = vec![];
+    let mut height = chain.len().checked_sub(1);
+    let mut step = 1;
+    while let Some(current) = height {
+        if let Some(block) = chain.blocks().get_by_height(current) {
+            locator.push(block.hash());
+        }
+        if locator.len() >= 10 {
+            step *= 2;
+        }
+        height = match current {
+            0 => None,
+            _ => Some(current.saturating_sub(step)),
+        };
+    }
+    locator
+}
+
+fn headers_after(chain: &Blockchain, locator: &[Hash]) -> Vec<BlockHeader> {
+    let blocks = chain.blocks();
+    let start = locator
+        .iter()
+        .find(|hash| blocks.is_active(hash))
+        .and_then(|hash| blocks.height(hash))
+        .map_or(0, |height| height + 1);
+    (start..chain.len())
+        .take(MAX_HEADERS)
+        .filter_map(|height| blocks.get_by_height(height))
+        .map(|block| block.header().clone())
+        .collect()
+}
+
+fn missing_blocks(chain: &Blockchain, headers: &[BlockHeader]) -> Result<Vec<Hash>, Error> {
+    let first = match headers.first() {
+        Some(first) => first,
+        None => return Ok(vec![]),
+    };
+    let connects = match first.prev_hash() {
+        Some(prev_hash) => chain.blocks().contains(prev_hash),
+        None => chain.is_empty() || genesis_hash(chain) == Some(first.hash()),
+    };
+    if !connects {
+        return Err("Headers don't connect to the chain.".to_string());
+    }
+
+    let mut prev_hash = first.prev_hash().cloned();
+    let mut missing = vec![];
+    for header in headers {
+        if header.prev_hash() != prev_hash.as_ref() {
+            return Err("Headers aren't linked.".to_string());
+        }
+        let hash = header.hash();
+        if !chain.blocks().contains(&hash) {
+            missing.push(hash.clone());
+        }
+        prev_hash = Some(hash);
+    }
+    chain.check_headers(headers).map_err(|error| error.to_string())?;
+    Ok(missing)
+}
+
+fn write_message(stream: &mut TcpStream, message: &Message) -> io::Result<()> {
+    let payload = message.to_bytes();
+    stream.write_all(&(payload.len() as u32).to_be_bytes())?;
+    stream.write_all(&payload)?;
+    stream.flush()
+}
+
+fn read_message(stream: &mut TcpStream) -> io::Result<Message> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Message too large."));
+    }
+
+    // The buffer grows with the bytes that actually arrive instead of being
+    // allocated up front from a length the peer chose.
+    let mut payload = vec![];
+    stream.take(len as u64).read_to_end(&mut payload)?;
+    if payload.len() != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated message."));
+    }
+    Message::from_bytes(&payload).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::append_block;
+
+    use super::*;
+
+    #[test]
+    fn test_locator_and_headers() {
+        let mut bc = Blockchain::new();
+        assert!(locator(&bc).is_empty());
+        for _ in 0..30 {
+            append_block(&mut bc);
+        }
+
+        let locator = locator(&bc);
+        let hash_at = |height: usize| bc.blocks().get_by_height(height).unwrap().hash();
+        assert_eq!(locator[0], hash_at(29));
+        assert_eq!(locator[9], hash_at(20));
+        assert_eq!(locator[10], hash_at(18));
+        assert_eq!(locator.last(), Some(&hash_at(0)));
+
+        assert_eq!(headers_after(&bc, &locator).len(), 0);
+        assert_eq!(headers_after(&bc, &[hash_at(25)]).len(), 4);
+        assert_eq!(headers_after(&bc, &["ab".repeat(32)]).len(), 30);
+
+        let headers = headers_after(&bc, &[hash_at(25)]);
+        assert_eq!(missing_blocks(&bc, &headers), Ok(vec![]));
+        assert_eq!(missing_blocks(&Blockchain::new(), &headers_after(&bc, &[])).map(|m| m.len()), Ok(30));
+        assert!(missing_blocks(&Blockchain::new(), &headers).is_err());
+        let mut unlinked = headers_after(&bc, &[]);
+        unlinked.remove(1);
+        assert!(missing_blocks(&Blockchain::new(), &unlinked).is_err());
+
+        let mut easy = headers_after(&bc, &[hash_at(25)]);
+        easy.last_mut().unwrap().bits = 0x2000ffff;
+        assert_eq!(
+            missing_blocks(&bc, &easy),
+            Err(BlockError::InvalidBits {
+                expected: bc.target().to_compact(),
+                got: 0x2000ffff,
+            }
+            .to_string())
+        );
+    }
+}
@@ -0,0 +1,496 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::traits::{Decode, Encode, Hashable, WorldState};
+use crate::types::{AccountType, Block, Blockchain, RpcError, SharedChain, Transaction, TransactionData};
+
+const MAX_BODY_LEN: usize = 1 << 20;
+const MAX_HEAD_LEN: u64 = 8 << 10;
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub struct RpcServer {
+    local_addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl RpcServer {
+    pub fn start(chain: SharedChain, addr: SocketAddr) -> io::Result<Self> {
+        if !addr.ip().is_loopback() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "RPC server only binds to loopback addresses.",
+            ));
+        }
+
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let server_shutdown = shutdown.clone();
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if server_shutdown.load(Ordering::Relaxed) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let chain = chain.clone();
+                    thread::spawn(move || {
+                        let _ = handle_connection(&chain, stream);
+                    });
+                }
+            }
+        });
+
+        Ok(Self {
+            local_addr,
+            shutdown,
+            handle,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn join(self) {
+        let _ = self.handle.join();
+    }
+
+    pub fn stop(self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        // Wakes up the blocking accept so the listener sees the flag.
+        let _ = TcpStream::connect(self.local_addr);
+        self.join();
+    }
+}
+
+fn handle_connection(chain: &Mutex<Blockchain>, stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    // The request line and headers share one byte budget.
+    let mut head = (&mut reader).take(MAX_HEAD_LEN);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+
+    let mut complete = false;
+    let mut content_length = 0;
+    let mut content_type = None;
+    let mut host = None;
+    loop {
+        let mut line = String::new();
+        if head.read_line(&mut line)? == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            complete = line.ends_with('\n');
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let (name, value) = (name.trim(), value.trim().to_string());
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("content-type") {
+                content_type = Some(value);
+            } else if name.eq_ignore_ascii_case("host") {
+                host = Some(value);
+            }
+        }
+    }
+
+    // Browsers can be made to send requests to localhost, so only plain JSON
+    // requests addressed to a loopback host are served.
+    let is_json = content_type.is_some_and(|value| {
+        value.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case("application/json")
+    });
+    let (status, body) = if !complete {
+        ("431 Request Header Fields Too Large", String::new())
+    } else if !request_line.starts_with("POST ") {
+        ("405 Method Not Allowed", String::new())
+    } else if !host.as_deref().is_some_and(is_loopback_host) {
+        ("403 Forbidden", String::new())
+    } else if !is_json {
+        ("415 Unsupported Media Type", String::new())
+    } else if content_length > MAX_BODY_LEN {
+        ("413 Payload Too Large", String::new())
+    } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        ("200 OK", handle_request(chain, &String::from_utf8_lossy(&body)))
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+
+    // Closing with unread request bytes would reset the connection and could
+    // drop the response, so whatever the client still sends is drained.
+    stream.shutdown(Shutdown::Write)?;
+    io::copy(&mut reader.take(MAX_BODY_LEN as u64), &mut io::sink())?;
+    Ok(())
+}
+
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(""),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost") || name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+pub fn handle_request(chain: &Mutex<Blockchain>, body: &str) -> String {
+    let (id, result) = match serde_json::from_str::<Value>(body) {
+        Ok(request) => {
+            let id = request.get("id").cloned().unwrap_or(Value::Null);
+            (id, dispatch(chain, &request))
+        }
+        Err(_) => (Value::Null, Err(RpcError::ParseError)),
+    };
+
+    let response = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": error.code(), "message": error.to_string() },
+        }),
+    };
+    response.to_string()
+}
+
+fn dispatch(chain: &Mutex<Blockchain>, request: &Value) -> Result<Value, RpcError> {
+    let method = request
+        .get("method")
+        .and_then(Value::as_str)
+        .ok_or(RpcError::InvalidRequest)?;
+    let params = match request.get("params") {
+        None => vec![],
+        Some(Value::Array(params)) => params.clone(),
+        Some(_) => return Err(RpcError::InvalidRequest),
+    };
+
+    let lock = || {
+        chain.lock().map_err(|_| RpcError::Internal {
+            message: "Chain lock poisoned.".to_string(),
+        })
+    };
+
+    // The replay runs on a copy so other requests and the node aren't
+    // blocked while it validates every block.
+    if method == "validate_chain" {
+        let snapshot = lock()?.snapshot();
+        snapshot
+            .validate_full()
+            .map_err(|error| RpcError::InvalidChain { error })?;
+        return Ok(json!(true));
+    }
+
+    let mut chain = lock()?;
+    match method {
+        "get_balance" => {
+            let account_id = string_param(&params, 0)?;
+            let account = chain
+                .get_account_by_id(account_id.clone())
+                .ok_or(RpcError::UnknownAccount { account_id })?;
+            Ok(json!({
+                "balance": account.balance().to_string(),
+                "locked": account.locked_balance().to_string(),
+            }))
+        }
+        "get_account" => {
+            let account_id = string_param(&params, 0)?;
+            let account = chain
+                .get_account_by_id(account_id.clone())
+                .ok_or(RpcError::UnknownAccount {
+                    account_id: account_id.clone(),
+                })?;
+            let account_type = match account.account_type() {
+                AccountType::User => "user",
+                AccountType::Contract => "contract",
+                AccountType::Multisig => "multisig",
+            };
+            Ok(json!({
+                "account_id": account_id,
+                "account_type": account_type,
+                "balance": account.balance().to_string(),
+                "locked": account.locked_balance().to_string(),
+                "nonce": account.nonce().to_string(),
+                "public_keys": account.public_keys().iter().map(hex::encode).collect::<Vec<_>>(),
+                "threshold": account.threshold(),
+            }))
+        }
+        "get_account_proof" => {
+            let account_id = string_param(&params, 0)?;
+            let tip = chain.blocks().tip().ok_or(RpcError::UnknownBlock)?;
+            Ok(json!({
+                "block": tip.hash(),
+                "header": hex::encode(tip.header().to_bytes()),
+                "proof": hex::encode(chain.prove_account(account_id).to_bytes()),
+            }))
+        }
+        "get_block_by_height" => {
+            let height = params
+                .first()
+                .and_then(Value::as_u64)
+                .ok_or_else(|| invalid_params("expected a block height"))?;
+            let block = chain
+                .blocks()
+                .get_by_height(height as usize)
+                .ok_or(RpcError::UnknownBlock)?;
+            Ok(block_json(&chain, block))
+        }
+        "get_block_by_hash" => {
+            let hash = string_param(&params, 0)?;
+            let block = chain.blocks().get(&hash).ok_or(RpcError::UnknownBlock)?;
+            Ok(block_json(&chain, block))
+        }
+        "send_transaction" => {
+            let raw = string_param(&params, 0)?;
+            let bytes = hex::decode(&raw).map_err(|_| invalid_params("transaction isn't valid hex"))?;
+            let tx = Transaction::from_bytes(&bytes).map_err(|error| invalid_params(&error))?;
+            let hash = tx.hash();
+            chain
+                .submit_transaction(tx)
+                .map_err(|error| RpcError::TransactionRejected { error })?;
+            Ok(json!(hash))
+        }
+        "get_chain_info" => Ok(json!({
+            "height": chain.len().checked_sub(1),
+            "tip": chain.get_last_block_hash(),
+            "target": chain.target().to_string(),
+            "difficulty": chain.difficulty(),
+            "issued_supply": chain.issued_supply().to_string(),
+            "median_time_past": chain.median_time_past(),
+            "pending_transactions": chain.transaction_pool().len(),
+        })),
+        _ => Err(RpcError::MethodNotFound {
+            method: method.to_string(),
+        }),
+    }
+}
+
+fn invalid_params(message: &str) -> RpcError {
+    RpcError::InvalidParams {
+        message: message.to_string(),
+    }
+}
+
+fn string_param(params: &[Value], index: usize) -> Result<String, RpcError> {
+    params
+        .get(index)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| invalid_params(&format!("expected a string at position {}", index)))
+}
+
+fn block_json(chain: &Blockchain, block: &Block) -> Value {
+    let hash = block.hash();
+    let header = block.header();
+    let transactions: Vec<Value> = block.transactions().iter().map(transaction_json).collect();
+    json!({
+        "hash": hash,
+        "height": chain.blocks().height(&hash),
+        "active": chain.blocks().is_active(&hash),
+        "prev_hash": header.prev_hash(),
+        "merkle_root": header.merkle_root(),
+        "state_root": header.state_root(),
+        "timestamp": header.timestamp(),
+        "bits": header.bits(),
+        "extra_nonce": header.extra_nonce(),
+        "nonce": header.nonce().to_string(),
+        "transactions": transactions,
+        "raw": hex::encode(block.to_bytes()),
+    })
+}
+
+fn transaction_json(tx: &Transaction) -> Value {
+    let data = match &tx.data {
+        TransactionData::CreateAccount(account_id, public_key) => json!({
+            "type": "create_account",
+            "account_id": account_id,
+            "public_key": hex::encode(public_key),
+        }),
+        TransactionData::CreateMultisigAccount {
+            account_id,
+            public_keys,
+            threshold,
+        } => json!({
+            "type": "create_multisig_account",
+            "account_id": account_id,
+            "public_keys": public_keys.iter().map(hex::encode).collect::<Vec<_>>(),
+            "threshold": threshold,
+        }),
+        TransactionData::MintInitialSupply { to, amount } => json!({
+            "type": "mint_initial_supply",
+            "to": to,
+            "amount": amount.to_string(),
+        }),
+        TransactionData::Transfer { to, amount, fee } => json!({
+            "type": "transfer",
+            "to": to,
+            "amount": amount.to_string(),
+            "fee": fee.to_string(),
+        }),
+        TransactionData::Coinbase { to, amount } => json!({
+            "type": "coinbase",
+            "to": to,
+            "amount": amount.to_string(),
+        }),
+    };
+    json!({
+        "hash": tx.hash(),
+        "from": tx.from,
+        "nonce": tx.nonce.to_string(),
+        "data": data,
+        "raw": hex::encode(tx.to_bytes()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state_tree::AccountProof;
+    use crate::types::BlockHeader;
+    use crate::utils::{append_block_with_tx, create_account_tx, create_transfer_tx, mint_initial_supply};
+
+    use super::*;
+
+    fn chain() -> (SharedChain, ed25519_dalek::Keypair) {
+        let mut bc = Blockchain::new();
+        let (keypair, tx_create_satoshi) = create_account_tx("satoshi".to_string());
+        let (_, tx_create_alice) = create_account_tx("alice".to_string());
+        append_block_with_tx(&mut bc, vec![
+            tx_create_satoshi,
+            mint_initial_supply("satoshi".to_string(), 1_000),
+            tx_create_alice,
+        ])
+        .unwrap();
+        (Arc::new(Mutex::new(bc)), keypair)
+    }
+
+    fn call(chain: &SharedChain, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        serde_json::from_str(&handle_request(chain, &request.to_string())).unwrap()
+    }
+
+    fn error_code(response: &Value) -> Option<i64> {
+        response["error"]["code"].as_i64()
+    }
+
+    #[test]
+    fn test_queries() {
+        let (chain, _) = chain();
+
+        let balance = call(&chain, "get_balance", json!(["satoshi"]));
+        assert_eq!(balance["id"], json!(1));
+        assert_eq!(balance["result"], json!({ "balance": "1000", "locked": "0" }));
+        assert_eq!(call(&chain, "get_account", json!(["alice"]))["result"]["nonce"], json!("0"));
+        assert_eq!(error_code(&call(&chain, "get_balance", json!(["bob"]))), Some(-32001));
+        assert_eq!(error_code(&call(&chain, "get_balance", json!([7]))), Some(-32602));
+
+        let block = call(&chain, "get_block_by_height", json!([0]))["result"].clone();
+        assert_eq!(block["height"], json!(0));
+        assert_eq!(block["transactions"][1]["data"]["type"], json!("mint_initial_supply"));
+        let raw = hex::decode(block["raw"].as_str().unwrap()).unwrap();
+        assert_eq!(Some(&Block::from_bytes(&raw).unwrap()), chain.lock().unwrap().blocks().get_by_height(0));
+        assert_eq!(call(&chain, "get_block_by_hash", json!([block["hash"]]))["result"], block);
+        assert_eq!(error_code(&call(&chain, "get_block_by_height", json!([1]))), Some(-32002));
+
+        let result = call(&chain, "get_account_proof", json!(["satoshi"]))["result"].clone();
+        assert_eq!(result["block"], block["hash"]);
+        let header = BlockHeader::from_bytes(&hex::decode(result["header"].as_str().unwrap()).unwrap()).unwrap();
+        let proof = AccountProof::from_bytes(&hex::decode(result["proof"].as_str().unwrap()).unwrap()).unwrap();
+        assert!(proof.verify(&header));
+        assert_eq!(proof.account.unwrap().balance(), 1_000);
+
+        let info = call(&chain, "get_chain_info", json!([]))["result"].clone();
+        assert_eq!(info["height"], json!(0));
+        assert_eq!(info["tip"], block["hash"]);
+        assert_eq!(call(&chain, "validate_chain", json!([]))["result"], json!(true));
+    }
+
+    #[test]
+    fn test_send_transaction() {
+        let (chain, keypair) = chain();
+
+        let mut tx = create_transfer_tx("satoshi".to_string(), "alice".to_string(), 10, 1, 0);
+        tx.sign(&keypair);
+        let raw = hex::encode(tx.to_bytes());
+        assert_eq!(call(&chain, "send_transaction", json!([raw]))["result"], json!(tx.hash()));
+        assert_eq!(call(&chain, "get_chain_info", json!([]))["result"]["pending_transactions"], json!(1));
+        assert_eq!(error_code(&call(&chain, "send_transaction", json!([raw]))), Some(-32003));
+
+        let unsigned = create_transfer_tx("satoshi".to_string(), "alice".to_string(), 10, 1, 1);
+        let raw = hex::encode(unsigned.to_bytes());
+        assert_eq!(error_code(&call(&chain, "send_transaction", json!([raw]))), Some(-32003));
+        assert_eq!(error_code(&call(&chain, "send_transaction", json!(["zz"]))), Some(-32602));
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let (chain, _) = chain();
+
+        let response: Value = serde_json::from_str(&handle_request(&chain, "{")).unwrap();
+        assert_eq!(error_code(&response), Some(-32700));
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(error_code(&call(&chain, "get_everything", json!([]))), Some(-32601));
+
+        let response: Value = serde_json::from_str(&handle_request(&chain, r#"{"id": 3}"#)).unwrap();
+        assert_eq!(error_code(&response), Some(-32600));
+        assert_eq!(response["id"], json!(3));
+    }
+
+    fn post(addr: SocketAddr, headers: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "POST / HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}",
+            headers,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_http() {
+        let (chain, _) = chain();
+        assert!(RpcServer::start(chain.clone(), "0.0.0.0:0".parse().unwrap()).is_err());
+        let server = RpcServer::start(chain, "127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = server.local_addr();
+
+        let body = r#"{"jsonrpc":"2.0","id":"a","method":"get_balance","params":["satoshi"]}"#;
+        let json = "Content-Type: application/json; charset=utf-8\r\n";
+        let response = post(addr, &format!("Host: localhost:8545\r\n{}", json), body);
+        assert!(post(addr, &format!("Host: [::1]:8545\r\n{}", json), body).starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(post(addr, &format!("Host: evil.example\r\n{}", json), body).starts_with("HTTP/1.1 403 "));
+        assert!(post(addr, json, body).starts_with("HTTP/1.1 403 "));
+        assert!(post(addr, "Host: 127.0.0.1\r\nContent-Type: text/plain\r\n", body).starts_with("HTTP/1.1 415 "));
+        let oversized = format!("Host: 127.0.0.1\r\n{}X-Padding: {}\r\n", json, "a".repeat(MAX_HEAD_LEN as usize));
+        assert!(post(addr, &oversized, body).starts_with("HTTP/1.1 431 "));
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["id"], json!("a"));
+        assert_eq!(body["result"]["balance"], json!("1000"));
+
+        server.stop();
+    }
+}
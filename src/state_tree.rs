@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use blake2::{Blake2s, Digest};
+use blake2::digest::FixedOutput;
+
+use crate::encoding::{invalid_tag, Reader};
+use crate::traits::{Decode, Encode};
+use crate::types::{Account, AccountId, BlockHeader, Error, Hash};
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+const EMPTY: [u8; 32] = [0; 32];
+
+pub type StateKey = [u8; 32];
+
+// Path from an account's key to the state root. `account` is None for a
+// non-membership proof, which ends either in an empty subtree or in the leaf
+// of another account sharing the path, given as its key and account hash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountProof {
+    pub account_id: AccountId,
+    pub account: Option<Account>,
+    pub other_leaf: Option<(Hash, Hash)>,
+    pub siblings: Vec<Hash>,
+}
+
+impl AccountProof {
+    pub fn verify(&self, header: &BlockHeader) -> bool {
+        self.verify_root(header.state_root())
+    }
+
+    pub fn verify_root(&self, root: &Hash) -> bool {
+        let key = account_key(&self.account_id);
+        let depth = self.siblings.len();
+        if depth > key.len() * 8 {
+            return false;
+        }
+
+        let mut node = match (&self.account, &self.other_leaf) {
+            (Some(account), None) => hash_leaf(&key, &account_hash(account)),
+            (None, None) => EMPTY,
+            (None, Some((other_key, other_hash))) => {
+                let other_key: StateKey = match hex::decode(other_key).map(|bytes| bytes.try_into()) {
+                    Ok(Ok(other_key)) => other_key,
+                    _ => return false,
+                };
+                let shares_path = (0..depth).all(|depth| bit(&other_key, depth) == bit(&key, depth));
+                match hex::decode(other_hash) {
+                    Ok(other_hash) if other_key != key && shares_path => hash_leaf(&other_key, &other_hash),
+                    _ => return false,
+                }
+            }
+            (Some(_), Some(_)) => return false,
+        };
+
+        for (index, sibling) in self.siblings.iter().enumerate() {
+            let sibling = match hex::decode(sibling) {
+                Ok(sibling) => sibling,
+                Err(_) => return false,
+            };
+            node = if bit(&key, depth - 1 - index) {
+                hash_node(&sibling, &node)
+            } else {
+                hash_node(&node, &sibling)
+            };
+        }
+
+        &hex::encode(node) == root
+    }
+}
+
+impl Encode for AccountProof {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.account_id.encode(out);
+        self.account.encode(out);
+        match &self.other_leaf {
+            Some((other_key, other_hash)) => {
+                out.push(1);
+                other_key.encode(out);
+                other_hash.encode(out);
+            }
+            None => out.push(0),
+        }
+        self.siblings.encode(out);
+    }
+}
+
+impl Decode for AccountProof {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        let account_id = AccountId::decode(reader)?;
+        let account = Option::<Account>::decode(reader)?;
+        let other_leaf = match reader.read_tag()? {
+            0 => None,
+            1 => Some((Hash::decode(reader)?, Hash::decode(reader)?)),
+            tag => return Err(invalid_tag(tag)),
+        };
+        Ok(Self {
+            account_id,
+            account,
+            other_leaf,
+            siblings: Vec::<Hash>::decode(reader)?,
+        })
+    }
+}
+
+// Sparse Merkle tree over Blake2s(account_id). An empty subtree hashes to
+// zero and a subtree holding a single account collapses to that account's
+// leaf, so only the paths that separate accounts are ever hashed. The nodes
+// are kept, so changing an account only rehashes the path above its leaf.
+#[derive(Debug, Clone, Default)]
+pub struct StateTree {
+    root: Node,
+}
+
+#[derive(Debug, Clone, Default)]
+enum Node {
+    #[default]
+    Empty,
+    Leaf {
+        key: StateKey,
+        account_hash: [u8; 32],
+        hash: [u8; 32],
+    },
+    Branch {
+        left: Box<Node>,
+        right: Box<Node>,
+        hash: [u8; 32],
+    },
+}
+
+impl Node {
+    fn leaf(key: StateKey, account_hash: [u8; 32]) -> Self {
+        Node::Leaf {
+            key,
+            account_hash,
+            hash: hash_leaf(&key, &account_hash),
+        }
+    }
+
+    // Joins two subtrees, collapsing the result to a leaf or to nothing when
+    // fewer than two accounts are left under it.
+    fn branch(left: Node, right: Node) -> Self {
+        match (left, right) {
+            (Node::Empty, Node::Empty) => Node::Empty,
+            (leaf @ Node::Leaf { .. }, Node::Empty) | (Node::Empty, leaf @ Node::Leaf { .. }) => leaf,
+            (left, right) => Node::Branch {
+                hash: hash_node(&left.hash(), &right.hash()),
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+        }
+    }
+
+    fn hash(&self) -> [u8; 32] {
+        match self {
+            Node::Empty => EMPTY,
+            Node::Leaf { hash, .. } | Node::Branch { hash, .. } => *hash,
+        }
+    }
+
+    fn update(self, key: &StateKey, account_hash: Option<[u8; 32]>, depth: usize) -> Self {
+        match (self, account_hash) {
+            (Node::Branch { left, right, .. }, _) if bit(key, depth) => {
+                Node::branch(*left, right.update(key, account_hash, depth + 1))
+            }
+            (Node::Branch { left, right, .. }, _) => {
+                Node::branch(left.update(key, account_hash, depth + 1), *right)
+            }
+            (Node::Leaf { key: leaf_key, .. }, None) if leaf_key == *key => Node::Empty,
+            (Node::Leaf { key: leaf_key, .. }, Some(account_hash)) if leaf_key == *key => {
+                Node::leaf(*key, account_hash)
+            }
+            (Node::Empty, Some(account_hash)) => Node::leaf(*key, account_hash),
+            (other @ Node::Leaf { .. }, Some(account_hash)) => {
+                Node::split(other, Node::leaf(*key, account_hash), depth)
+            }
+            (node, None) => node,
+        }
+    }
+
+    // Pushes two leaves down until their keys first differ.
+    fn split(a: Node, b: Node, depth: usize) -> Self {
+        let (a_bit, b_bit) = match (&a, &b) {
+            (Node::Leaf { key: a_key, .. }, Node::Leaf { key: b_key, .. }) => (bit(a_key, depth), bit(b_key, depth)),
+            _ => unreachable!("only leaves are split"),
+        };
+        match (a_bit, b_bit) {
+            (false, true) => Node::branch(a, b),
+            (true, false) => Node::branch(b, a),
+            (false, false) => Node::branch(Node::split(a, b, depth + 1), Node::Empty),
+            (true, true) => Node::branch(Node::Empty, Node::split(a, b, depth + 1)),
+        }
+    }
+}
+
+impl StateTree {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn from_accounts(accounts: &HashMap<AccountId, Account>) -> Self {
+        let mut tree = Self::new();
+        for (account_id, account) in accounts {
+            tree.update(account_id, Some(account));
+        }
+        tree
+    }
+
+    pub fn root(&self) -> Hash {
+        hex::encode(self.root.hash())
+    }
+
+    // Sets the leaf for `account_id`, or removes it when `account` is None.
+    pub fn update(&mut self, account_id: &str, account: Option<&Account>) {
+        let key = account_key(account_id);
+        let root = std::mem::take(&mut self.root);
+        self.root = root.update(&key, account.map(account_hash), 0);
+    }
+
+    // `account` has to be the one the tree holds for `account_id`, if any.
+    pub fn prove(&self, account_id: &str, account: Option<&Account>) -> AccountProof {
+        let key = account_key(account_id);
+        let mut node = &self.root;
+        let mut siblings = vec![];
+        let mut depth = 0;
+        while let Node::Branch { left, right, .. } = node {
+            let (next, sibling) = if bit(&key, depth) { (right, left) } else { (left, right) };
+            siblings.push(hex::encode(sibling.hash()));
+            node = next;
+            depth += 1;
+        }
+        siblings.reverse();
+
+        let (account, other_leaf) = match node {
+            Node::Leaf { key: leaf_key, .. } if *leaf_key == key => (account.cloned(), None),
+            Node::Leaf {
+                key: leaf_key,
+                account_hash,
+                ..
+            } => (None, Some((hex::encode(leaf_key), hex::encode(account_hash)))),
+            _ => (None, None),
+        };
+
+        AccountProof {
+            account_id: account_id.to_string(),
+            account,
+            other_leaf,
+            siblings,
+        }
+    }
+}
+
+pub fn account_key(account_id: &str) -> StateKey {
+    Blake2s::digest(account_id.as_bytes()).into()
+}
+
+fn bit(key: &StateKey, depth: usize) -> bool {
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+fn account_hash(account: &Account) -> [u8; 32] {
+    Blake2s::digest(&account.to_bytes()).into()
+}
+
+fn hash_leaf(key: &StateKey, account_hash: &[u8]) -> [u8; 32] {
+    let mut hasher = Blake2s::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(key);
+    hasher.update(account_hash);
+    hasher.finalize_fixed().into()
+}
+
+fn hash_node(left: &[u8], right: &[u8]) -> [u8; 32] {
+    let mut hasher = Blake2s::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize_fixed().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::{AccountType, Balance};
+
+    use super::*;
+
+    fn accounts(ids: &[&str]) -> HashMap<AccountId, Account> {
+        ids.iter()
+            .map(|id| (id.to_string(), Account::new(AccountType::User, [1; 32])))
+            .collect()
+    }
+
+    fn state_root(accounts: &HashMap<AccountId, Account>) -> Hash {
+        StateTree::from_accounts(accounts).root()
+    }
+
+    // The root computed from scratch over the sorted leaves.
+    fn reference_root(accounts: &HashMap<AccountId, Account>) -> Hash {
+        fn subtree_root(leaves: &[(StateKey, [u8; 32])], depth: usize) -> [u8; 32] {
+            match leaves {
+                [] => EMPTY,
+                [(_, leaf)] => *leaf,
+                _ => {
+                    let split = leaves.partition_point(|(key, _)| !bit(key, depth));
+                    hash_node(
+                        &subtree_root(&leaves[..split], depth + 1),
+                        &subtree_root(&leaves[split..], depth + 1),
+                    )
+                }
+            }
+        }
+
+        let mut leaves: Vec<_> = accounts
+            .iter()
+            .map(|(account_id, account)| {
+                let key = account_key(account_id);
+                (key, hash_leaf(&key, &account_hash(account)))
+            })
+            .collect();
+        leaves.sort_unstable_by_key(|(key, _)| *key);
+        hex::encode(subtree_root(&leaves, 0))
+    }
+
+    #[test]
+    fn test_state_root() {
+        assert_eq!(state_root(&HashMap::new()), "00".repeat(32));
+
+        let single = accounts(&["alice"]);
+        let key = account_key("alice");
+        assert_eq!(state_root(&single), hex::encode(hash_leaf(&key, &account_hash(&single["alice"]))));
+
+        let mut state = accounts(&["alice", "bob", "carol", "dave"]);
+        let root = state_root(&state);
+        assert_eq!(root, state_root(&accounts(&["dave", "carol", "bob", "alice"])));
+        assert_eq!(root, reference_root(&state));
+
+        state.get_mut("bob").unwrap().balance = 1;
+        let changed = state_root(&state);
+        assert_ne!(changed, root);
+
+        state.get_mut("bob").unwrap().balance = 0;
+        assert_eq!(state_root(&state), root);
+        state.remove("carol");
+        assert_ne!(state_root(&state), root);
+    }
+
+    #[test]
+    fn test_updates() {
+        let ids: Vec<String> = (0..64).map(|index| format!("account{}", index)).collect();
+        let mut state = HashMap::new();
+        let mut tree = StateTree::new();
+
+        for (index, account_id) in ids.iter().enumerate() {
+            let mut account = Account::new(AccountType::User, [1; 32]);
+            account.balance = index as Balance;
+            tree.update(account_id, Some(&account));
+            state.insert(account_id.clone(), account);
+            assert_eq!(tree.root(), reference_root(&state));
+        }
+
+        for account_id in ids.iter().step_by(3) {
+            let account = state.get_mut(account_id).unwrap();
+            account.balance += 1_000;
+            tree.update(account_id, Some(account));
+            assert_eq!(tree.root(), reference_root(&state));
+        }
+
+        // Removing accounts collapses the paths that no longer separate two.
+        for account_id in &ids {
+            state.remove(account_id);
+            tree.update(account_id, None);
+            assert_eq!(tree.root(), reference_root(&state));
+        }
+        assert_eq!(tree.root(), "00".repeat(32));
+
+        tree.update("missing", None);
+        assert_eq!(tree.root(), "00".repeat(32));
+    }
+
+    #[test]
+    fn test_proofs() {
+        let mut state = accounts(&["alice", "bob", "carol", "dave", "eve"]);
+        state.get_mut("carol").unwrap().balance = 500;
+        let tree = StateTree::from_accounts(&state);
+        let root = tree.root();
+
+        for account_id in state.keys() {
+            let proof = tree.prove(account_id, state.get(account_id));
+            assert_eq!(proof.account.as_ref(), state.get(account_id));
+            assert!(proof.verify_root(&root));
+            assert!(!proof.verify_root(&"00".repeat(32)));
+
+            let mut tampered = proof.clone();
+            tampered.account.as_mut().unwrap().balance += 1;
+            assert!(!tampered.verify_root(&root));
+
+            let mut tampered = proof.clone();
+            tampered.account = None;
+            assert!(!tampered.verify_root(&root));
+
+            assert_eq!(AccountProof::from_bytes(&proof.to_bytes()), Ok(proof));
+        }
+
+        let (mut empty_ends, mut leaf_ends) = (0, 0);
+        for index in 0..32 {
+            let account_id = format!("missing{}", index);
+            let proof = tree.prove(&account_id, None);
+            assert!(proof.account.is_none());
+            assert!(proof.verify_root(&root));
+            match proof.other_leaf {
+                Some(_) => leaf_ends += 1,
+                None => empty_ends += 1,
+            }
+
+            let mut tampered = proof.clone();
+            tampered.account_id = "alice".to_string();
+            assert!(!tampered.verify_root(&root));
+            assert_eq!(AccountProof::from_bytes(&proof.to_bytes()), Ok(proof));
+        }
+        assert!(empty_ends > 0 && leaf_ends > 0);
+
+        let proof = StateTree::new().prove("alice", None);
+        assert!(proof.siblings.is_empty());
+        assert!(proof.verify_root(&"00".repeat(32)));
+    }
+}
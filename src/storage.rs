@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use blake2::{Blake2s, Digest};
+
+use crate::traits::{Decode, Encode, Hashable};
+use crate::types::{Block, Hash, StorageError};
+
+const BLOCKS_FILE: &str = "blocks.dat";
+const INDEX_FILE: &str = "index.dat";
+const RECORD_HEADER_LEN: usize = 8;
+const INDEX_ENTRY_LEN: usize = 48;
+const HASH_LEN: usize = 32;
+
+// One index entry per stored block: its height on the chain it was appended
+// to, its hash and the offset of its record in the block file.
+#[derive(Debug, Clone, PartialEq)]
+struct IndexEntry {
+    height: usize,
+    hash: Hash,
+    offset: u64,
+}
+
+#[derive(Debug)]
+pub struct BlockStore {
+    blocks_file: File,
+    index_file: File,
+    entries: Vec<IndexEntry>,
+    positions: HashMap<Hash, usize>,
+    end: u64,
+}
+
+impl BlockStore {
+    pub fn open(path: &Path) -> Result<(Self, Vec<Block>), StorageError> {
+        fs::create_dir_all(path)?;
+        let mut blocks_file = open_file(&path.join(BLOCKS_FILE))?;
+        let mut index_file = open_file(&path.join(INDEX_FILE))?;
+
+        let mut data = vec![];
+        blocks_file.read_to_end(&mut data)?;
+        let mut stored_index = vec![];
+        index_file.read_to_end(&mut stored_index)?;
+        let index: Vec<IndexEntry> = stored_index
+            .chunks_exact(INDEX_ENTRY_LEN)
+            .map(decode_index_entry)
+            .collect();
+
+        // Every record is checksummed and decoded anyway for the replay, so
+        // its entry is recomputed here and the stored index is only kept when
+        // it matches, down to each block's hash.
+        let mut blocks = vec![];
+        let mut entries: Vec<IndexEntry> = vec![];
+        let mut positions = HashMap::new();
+        let mut position = 0;
+        while position < data.len() {
+            match read_record(&data[position..]) {
+                Some(Ok((payload, len))) => {
+                    let block = Block::from_bytes(payload).map_err(|error| {
+                        StorageError::Decode {
+                            offset: position as u64,
+                            error,
+                        }
+                    })?;
+                    let entry = IndexEntry {
+                        height: block
+                            .prev_hash()
+                            .and_then(|prev_hash| positions.get(prev_hash))
+                            .map_or(0, |parent: &usize| entries[*parent].height + 1),
+                        hash: block.hash(),
+                        offset: position as u64,
+                    };
+                    positions.insert(entry.hash.clone(), entries.len());
+                    entries.push(entry);
+                    blocks.push(block);
+                    position += len;
+                }
+                Some(Err(len)) if position + len < data.len() => {
+                    return Err(StorageError::Corrupted {
+                        offset: position as u64,
+                    });
+                }
+                _ => break,
+            }
+        }
+
+        if position < data.len() {
+            blocks_file.set_len(position as u64)?;
+            blocks_file.sync_all()?;
+        }
+
+        if entries != index || stored_index.len() != index.len() * INDEX_ENTRY_LEN {
+            let mut bytes = Vec::with_capacity(entries.len() * INDEX_ENTRY_LEN);
+            for entry in &entries {
+                bytes.extend_from_slice(&encode_index_entry(entry));
+            }
+            index_file.set_len(0)?;
+            index_file.seek(SeekFrom::Start(0))?;
+            index_file.write_all(&bytes)?;
+            index_file.sync_all()?;
+        }
+
+        let store = Self {
+            blocks_file,
+            index_file,
+            entries,
+            positions,
+            end: position as u64,
+        };
+        Ok((store, blocks))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.positions.contains_key(hash)
+    }
+
+    pub fn position_of(&self, hash: &Hash) -> Option<usize> {
+        self.positions.get(hash).copied()
+    }
+
+    pub fn height_of(&self, hash: &Hash) -> Option<usize> {
+        self.position_of(hash).map(|position| self.entries[position].height)
+    }
+
+    // Positions of the stored blocks at `height`, one per branch that reached
+    // it, in the order they were appended.
+    pub fn positions_at(&self, height: usize) -> Vec<usize> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.height == height)
+            .map(|(position, _)| position)
+            .collect()
+    }
+
+    pub fn read_block(&mut self, position: usize) -> Result<Option<Block>, StorageError> {
+        let offset = match self.entries.get(position) {
+            Some(entry) => entry.offset,
+            None => return Ok(None),
+        };
+
+        let mut header = [0; RECORD_HEADER_LEN];
+        self.blocks_file.seek(SeekFrom::Start(offset))?;
+        self.blocks_file.read_exact(&mut header)?;
+
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let mut payload = vec![0; len];
+        self.blocks_file.read_exact(&mut payload)?;
+        if header[4..] != checksum(&payload) {
+            return Err(StorageError::Corrupted { offset });
+        }
+        Block::from_bytes(&payload)
+            .map(Some)
+            .map_err(|error| StorageError::Decode { offset, error })
+    }
+
+    pub fn append(&mut self, block: &Block, height: usize) -> Result<(), StorageError> {
+        let payload = block.to_bytes();
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+        record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        record.extend_from_slice(&checksum(&payload));
+        record.extend_from_slice(&payload);
+
+        self.blocks_file.seek(SeekFrom::Start(self.end))?;
+        self.blocks_file.write_all(&record)?;
+        self.blocks_file.sync_data()?;
+
+        let entry = IndexEntry {
+            height,
+            hash: block.hash(),
+            offset: self.end,
+        };
+        self.index_file.seek(SeekFrom::End(0))?;
+        self.index_file.write_all(&encode_index_entry(&entry))?;
+        self.index_file.sync_data()?;
+
+        self.positions.insert(entry.hash.clone(), self.entries.len());
+        self.entries.push(entry);
+        self.end += record.len() as u64;
+        Ok(())
+    }
+}
+
+fn open_file(path: &Path) -> Result<File, StorageError> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    Ok(file)
+}
+
+fn checksum(payload: &[u8]) -> [u8; 4] {
+    let digest = Blake2s::digest(payload);
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+// Returns the payload and full record length, or Err(record length) when the
+// checksum doesn't match. None means the record runs past the end of data.
+fn read_record(data: &[u8]) -> Option<Result<(&[u8], usize), usize>> {
+    if data.len() < RECORD_HEADER_LEN {
+        return None;
+    }
+    let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let end = RECORD_HEADER_LEN.checked_add(len)?;
+    if data.len() < end {
+        return None;
+    }
+
+    let payload = &data[RECORD_HEADER_LEN..end];
+    if data[4..RECORD_HEADER_LEN] != checksum(payload) {
+        return Some(Err(end));
+    }
+    Some(Ok((payload, end)))
+}
+
+fn encode_index_entry(entry: &IndexEntry) -> [u8; INDEX_ENTRY_LEN] {
+    let mut bytes = [0; INDEX_ENTRY_LEN];
+    bytes[..8].copy_from_slice(&(entry.height as u64).to_be_bytes());
+    bytes[8..16].copy_from_slice(&entry.offset.to_be_bytes());
+    if let Ok(hash) = hex::decode(&entry.hash) {
+        let len = hash.len().min(HASH_LEN);
+        bytes[16..16 + len].copy_from_slice(&hash[..len]);
+    }
+    bytes
+}
+
+fn decode_index_entry(bytes: &[u8]) -> IndexEntry {
+    let mut height = [0; 8];
+    height.copy_from_slice(&bytes[..8]);
+    let mut offset = [0; 8];
+    offset.copy_from_slice(&bytes[8..16]);
+    IndexEntry {
+        height: u64::from_be_bytes(height) as usize,
+        hash: hex::encode(&bytes[16..16 + HASH_LEN]),
+        offset: u64::from_be_bytes(offset),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::utils::{create_account_tx, generate_account_id};
+
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("blockchain_workshop_{}", generate_account_id()))
+    }
+
+    fn block(prev_hash: Option<Hash>) -> Block {
+        let mut block = Block::new(prev_hash);
+        block.add_transaction(create_account_tx(generate_account_id()).1);
+        block
+    }
+
+    #[test]
+    fn test_append_and_reopen() {
+        let path = temp_dir();
+        let first = block(None);
+        let second = block(Some(first.hash()));
+
+        {
+            let (mut store, blocks) = BlockStore::open(&path).unwrap();
+            assert!(blocks.is_empty());
+            store.append(&first, 0).unwrap();
+            store.append(&second, 1).unwrap();
+        }
+
+        let (mut store, blocks) = BlockStore::open(&path).unwrap();
+        assert_eq!(blocks, vec![first.clone(), second.clone()]);
+        assert_eq!(store.position_of(&second.hash()), Some(1));
+        assert_eq!(store.read_block(0).unwrap(), Some(first));
+        assert_eq!(store.read_block(2).unwrap(), None);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_height_index() {
+        let path = temp_dir();
+        let first = block(None);
+        let second = block(Some(first.hash()));
+        let fork = block(Some(first.hash()));
+
+        {
+            let (mut store, _) = BlockStore::open(&path).unwrap();
+            store.append(&first, 0).unwrap();
+            store.append(&second, 1).unwrap();
+            store.append(&fork, 1).unwrap();
+        }
+
+        let index_path = path.join(INDEX_FILE);
+        let index = fs::read(&index_path).unwrap();
+        assert_eq!(index.len(), 3 * INDEX_ENTRY_LEN);
+
+        let (store, _) = BlockStore::open(&path).unwrap();
+        assert_eq!(store.height_of(&fork.hash()), Some(1));
+        assert_eq!(store.positions_at(1), vec![1, 2]);
+        assert_eq!(store.positions_at(2), Vec::<usize>::new());
+
+        // A lost or short index is rebuilt from the block file.
+        fs::write(&index_path, &index[..INDEX_ENTRY_LEN + 5]).unwrap();
+        let (store, _) = BlockStore::open(&path).unwrap();
+        assert_eq!(store.height_of(&fork.hash()), Some(1));
+        assert_eq!(fs::read(&index_path).unwrap(), index);
+
+        // So is an entry naming the wrong block.
+        let mut edited = index.clone();
+        edited[2 * INDEX_ENTRY_LEN + 16] ^= 0xff;
+        fs::write(&index_path, &edited).unwrap();
+        let (store, _) = BlockStore::open(&path).unwrap();
+        assert!(store.contains(&fork.hash()));
+        assert_eq!(fs::read(&index_path).unwrap(), index);
+
+        fs::remove_file(&index_path).unwrap();
+        let (store, _) = BlockStore::open(&path).unwrap();
+        assert_eq!(store.positions_at(1), vec![1, 2]);
+        assert_eq!(fs::read(&index_path).unwrap(), index);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_torn_record_truncated() {
+        let path = temp_dir();
+        let first = block(None);
+        let second = block(Some(first.hash()));
+
+        {
+            let (mut store, _) = BlockStore::open(&path).unwrap();
+            store.append(&first, 0).unwrap();
+            store.append(&second, 1).unwrap();
+        }
+
+        let blocks_path = path.join(BLOCKS_FILE);
+        let full_len = fs::metadata(&blocks_path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&blocks_path).unwrap();
+        file.set_len(full_len - 3).unwrap();
+
+        {
+            let (mut store, blocks) = BlockStore::open(&path).unwrap();
+            assert_eq!(blocks, vec![first.clone()]);
+            assert_eq!(store.position_of(&second.hash()), None);
+            assert_eq!(
+                fs::metadata(path.join(INDEX_FILE)).unwrap().len(),
+                INDEX_ENTRY_LEN as u64
+            );
+            store.append(&second, 1).unwrap();
+        }
+
+        let (_, blocks) = BlockStore::open(&path).unwrap();
+        assert_eq!(blocks, vec![first, second]);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupted_record_rejected() {
+        let path = temp_dir();
+        let first = block(None);
+        let second = block(Some(first.hash()));
+
+        {
+            let (mut store, _) = BlockStore::open(&path).unwrap();
+            store.append(&first, 0).unwrap();
+            store.append(&second, 1).unwrap();
+        }
+
+        let blocks_path = path.join(BLOCKS_FILE);
+        let mut data = fs::read(&blocks_path).unwrap();
+        data[RECORD_HEADER_LEN + 1] ^= 0xff;
+        fs::write(&blocks_path, data).unwrap();
+
+        assert_eq!(
+            BlockStore::open(&path).err().unwrap(),
+            StorageError::Corrupted { offset: 0 }
+        );
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
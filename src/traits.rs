@@ -1,16 +1,54 @@
-use crate::types::{Account, AccountId, AccountType, Error, Hash, PublicKeyBytes};
+use std::fmt;
+
+use crate::encoding::{Reader, ENCODING_VERSION};
+use crate::types::{
+    Account, AccountId, BlockHeader, Error, Hash, Target, TxError,
+};
 
 pub trait Hashable {
     fn hash(&self) -> Hash;
 }
 
+pub trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![ENCODING_VERSION];
+        self.encode(&mut out);
+        out
+    }
+}
+
+pub trait Decode: Sized {
+    fn decode(reader: &mut Reader) -> Result<Self, Error>;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(bytes);
+        let version = u8::decode(&mut reader)?;
+        if version != ENCODING_VERSION {
+            return Err(format!("Unsupported encoding version {}.", version));
+        }
+        let value = Self::decode(&mut reader)?;
+        if !reader.is_empty() {
+            return Err("Trailing bytes.".to_string());
+        }
+        Ok(value)
+    }
+}
+
 pub trait WorldState {
-    fn create_account(
-        &mut self,
-        account_id: AccountId,
-        account_type: AccountType,
-        public_key: PublicKeyBytes,
-    ) -> Result<(), Error>;
+    fn create_account(&mut self, account_id: AccountId, account: Account) -> Result<(), TxError>;
     fn get_account_by_id(&self, account_id: AccountId) -> Option<&Account>;
     fn get_account_by_id_mut(&mut self, account_id: AccountId) -> Option<&mut Account>;
 }
+
+pub trait DifficultyAlgorithm: fmt::Debug + Send + Sync {
+    fn max_target(&self) -> Target;
+
+    // How many of the most recent headers next_target needs.
+    fn window(&self) -> usize;
+
+    // Target for the block at `height`. `headers` are up to `window()`
+    // headers directly preceding it, oldest first.
+    fn next_target(&self, height: usize, genesis: &BlockHeader, headers: &[&BlockHeader]) -> Target;
+}
@@ -1,16 +1,22 @@
-use crate::types::{Balance, PublicKeyBytes};
+use crate::encoding::{invalid_tag, Reader};
+use crate::traits::{Decode, Encode};
+use crate::types::{Balance, Error, MAX_MULTISIG_KEYS, PublicKeyBytes, TxError};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AccountType {
     User,
     Contract,
+    Multisig,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Account {
     account_type: AccountType,
     pub(crate) balance: Balance,
-    pub(crate) public_key: PublicKeyBytes,
+    pub(crate) nonce: u128,
+    pub(crate) locked: Balance,
+    pub(crate) public_keys: Vec<PublicKeyBytes>,
+    pub(crate) threshold: u32,
 }
 
 impl Account {
@@ -18,7 +24,192 @@ impl Account {
         Self {
             account_type,
             balance: 0,
-            public_key,
+            nonce: 0,
+            locked: 0,
+            public_keys: vec![public_key],
+            threshold: 1,
         }
     }
+
+    pub fn new_multisig(public_keys: Vec<PublicKeyBytes>, threshold: u32) -> Self {
+        Self {
+            account_type: AccountType::Multisig,
+            balance: 0,
+            nonce: 0,
+            locked: 0,
+            public_keys,
+            threshold,
+        }
+    }
+
+    // Rules for a multisig key list, whether it comes from a transaction or
+    // from decoded account state.
+    pub(crate) fn check_multisig(public_keys: &[PublicKeyBytes], threshold: u32) -> Result<(), TxError> {
+        if public_keys.len() > MAX_MULTISIG_KEYS {
+            return Err(TxError::TooManyPublicKeys {
+                count: public_keys.len(),
+                max: MAX_MULTISIG_KEYS,
+            });
+        }
+        if threshold == 0 || threshold as usize > public_keys.len() {
+            return Err(TxError::InvalidThreshold {
+                threshold,
+                keys: public_keys.len(),
+            });
+        }
+        if public_keys
+            .iter()
+            .enumerate()
+            .any(|(index, key)| public_keys[..index].contains(key))
+        {
+            return Err(TxError::DuplicatePublicKey);
+        }
+        Ok(())
+    }
+
+    pub fn account_type(&self) -> &AccountType {
+        &self.account_type
+    }
+
+    pub fn balance(&self) -> Balance {
+        self.balance
+    }
+
+    pub fn locked_balance(&self) -> Balance {
+        self.locked
+    }
+
+    pub fn nonce(&self) -> u128 {
+        self.nonce
+    }
+
+    pub fn public_keys(&self) -> &[PublicKeyBytes] {
+        &self.public_keys
+    }
+
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+}
+
+impl Encode for AccountType {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(match self {
+            AccountType::User => 0,
+            AccountType::Contract => 1,
+            AccountType::Multisig => 2,
+        });
+    }
+}
+
+impl Decode for AccountType {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        match reader.read_tag()? {
+            0 => Ok(AccountType::User),
+            1 => Ok(AccountType::Contract),
+            2 => Ok(AccountType::Multisig),
+            tag => Err(invalid_tag(tag)),
+        }
+    }
+}
+
+impl Encode for Account {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.account_type.encode(out);
+        self.balance.encode(out);
+        self.nonce.encode(out);
+        self.locked.encode(out);
+        // Only multisig accounts carry a key list, every other account keeps
+        // the single public key it was encoded with before multisig existed.
+        match self.account_type {
+            AccountType::Multisig => {
+                self.public_keys.encode(out);
+                self.threshold.encode(out);
+            }
+            _ => self.public_keys[0].encode(out),
+        }
+    }
+}
+
+impl Decode for Account {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        let account_type = AccountType::decode(reader)?;
+        let balance = Balance::decode(reader)?;
+        let nonce = u128::decode(reader)?;
+        let locked = Balance::decode(reader)?;
+        let (public_keys, threshold) = match account_type {
+            AccountType::Multisig => (Vec::<PublicKeyBytes>::decode(reader)?, u32::decode(reader)?),
+            _ => (vec![PublicKeyBytes::decode(reader)?], 1),
+        };
+        Account::check_multisig(&public_keys, threshold).map_err(|error| error.to_string())?;
+        Ok(Self {
+            account_type,
+            balance,
+            nonce,
+            locked,
+            public_keys,
+            threshold,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding() {
+        let mut account = Account::new(AccountType::User, [7; 32]);
+        account.balance = 1_000;
+        account.nonce = 2;
+        account.locked = 50;
+
+        let bytes = account.to_bytes();
+        assert_eq!(
+            hex::encode(&bytes),
+            "0100".to_string()
+                + "000000000000000000000000000003e8"
+                + "00000000000000000000000000000002"
+                + "00000000000000000000000000000032"
+                + &"07".repeat(32)
+        );
+        assert_eq!(Account::from_bytes(&bytes), Ok(account));
+    }
+
+    #[test]
+    fn test_multisig_encoding() {
+        let mut account = Account::new_multisig(vec![[1; 32], [2; 32]], 2);
+        account.balance = 1_000;
+
+        let bytes = account.to_bytes();
+        assert_eq!(
+            hex::encode(&bytes),
+            "0102".to_string()
+                + "000000000000000000000000000003e8"
+                + "00000000000000000000000000000000"
+                + "00000000000000000000000000000000"
+                + "00000002" + &"01".repeat(32) + &"02".repeat(32)
+                + "00000002"
+        );
+        assert_eq!(Account::from_bytes(&bytes), Ok(account));
+    }
+
+    #[test]
+    fn test_invalid_multisig_decoding() {
+        let decode = |public_keys: Vec<PublicKeyBytes>, threshold| {
+            let mut account = Account::new_multisig(public_keys, threshold);
+            account.balance = 1_000;
+            Account::from_bytes(&account.to_bytes())
+        };
+
+        assert_eq!(
+            decode(vec![[1; 32], [2; 32]], 0),
+            Err("Invalid multisig threshold 0 for 2 public keys.".to_string())
+        );
+        assert!(decode(vec![[1; 32], [2; 32]], 3).is_err());
+        assert!(decode(vec![], 1).is_err());
+        assert!(decode(vec![[1; 32], [1; 32]], 1).is_err());
+        assert!(decode(vec![[1; 32]; 17], 1).is_err());
+        assert!(decode(vec![[1; 32], [2; 32]], 1).is_ok());
+    }
 }
@@ -1,69 +1,163 @@
-use blake2::{Blake2s, Digest};
-use blake2::digest::FixedOutput;
+use crate::merkle::{merkle_proof, merkle_root, MerkleProof};
+use crate::encoding::Reader;
+use crate::traits::{Decode, Encode, Hashable};
+use crate::types::{AccountId, Balance, Bits, BlockError, BlockHeader, BLOCK_VERSION, Error, Hash, HeaderHasher, MAX_NONCE, Target, Timestamp, Transaction, TransactionData};
+use crate::utils::{create_coinbase_tx, get_timestamp};
 
-use crate::traits::Hashable;
-use crate::types::{Bits, Hash, Target, Timestamp, Transaction};
-use crate::utils::{get_bits_from_hash, get_timestamp};
-
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Block {
-    nonce: u128,
-    timestamp: Timestamp,
+    pub(crate) header: BlockHeader,
     pub(crate) hash: Option<Hash>,
-    pub(crate) prev_hash: Option<Hash>,
     pub(crate) transactions: Vec<Transaction>,
 }
 
 impl Block {
     pub fn new(prev_hash: Option<Hash>) -> Self {
         let mut block = Block {
-            prev_hash,
-            timestamp: get_timestamp(),
+            header: BlockHeader {
+                version: BLOCK_VERSION,
+                prev_hash,
+                timestamp: get_timestamp(),
+                ..Default::default()
+            },
             ..Default::default()
         };
-        block.update_hash();
+        block.update_merkle_root();
         block
     }
 
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    pub fn prev_hash(&self) -> Option<&Hash> {
+        self.header.prev_hash.as_ref()
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        self.header.timestamp
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn beneficiary(&self) -> Option<&AccountId> {
+        match self.transactions.first().map(|tx| &tx.data) {
+            Some(TransactionData::Coinbase { to, .. }) => Some(to),
+            _ => None,
+        }
+    }
+
+    pub fn merkle_proof(&self, index: usize) -> Option<MerkleProof> {
+        merkle_proof(&self.transactions, index)
+    }
+
     pub fn set_nonce(&mut self, nonce: u128) {
-        self.nonce = nonce;
+        self.header.nonce = nonce;
+        self.update_hash();
+    }
+
+    pub fn set_extra_nonce(&mut self, extra_nonce: u64) {
+        self.header.extra_nonce = extra_nonce;
+        self.update_hash();
+    }
+
+    pub fn set_state_root(&mut self, state_root: Hash) {
+        self.header.state_root = state_root;
+        self.update_hash();
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: Timestamp) {
+        self.header.timestamp = timestamp;
+        self.update_hash();
+    }
+
+    pub fn set_bits(&mut self, bits: Bits) {
+        self.header.bits = bits;
         self.update_hash();
     }
 
     pub fn add_transaction(&mut self, transaction: Transaction) {
         self.transactions.push(transaction);
-        self.update_hash();
+        self.update_merkle_root();
+    }
+
+    pub fn add_coinbase(&mut self, beneficiary: AccountId, reward: Balance) {
+        if matches!(self.transactions.first(), Some(tx) if tx.is_coinbase()) {
+            self.transactions.remove(0);
+        }
+        let amount = reward.saturating_add(self.fees());
+        self.transactions.insert(0, create_coinbase_tx(beneficiary, amount));
+        self.update_merkle_root();
+    }
+
+    pub fn fees(&self) -> Balance {
+        self.transactions
+            .iter()
+            .fold(0, |fees: Balance, tx| fees.saturating_add(tx.fee()))
     }
 
     pub fn verify(&self) -> bool {
-        matches!(&self.hash, Some(hash) if hash == &self.hash())
+        self.header.merkle_root == merkle_root(&self.transactions)
+            && matches!(&self.hash, Some(hash) if hash == &self.hash())
+    }
+
+    fn update_merkle_root(&mut self) {
+        self.header.merkle_root = merkle_root(&self.transactions);
+        self.update_hash();
     }
 
     fn update_hash(&mut self) {
         self.hash = Some(self.hash());
     }
 
-    pub fn mine(&mut self, target: Target) {
-        let mut nonce = 1;
-        let target = Bits::from_str_radix(&target.clone(), 16).unwrap();
-        while !(get_bits_from_hash(self.hash.as_ref().unwrap().clone()) < target) {
-            nonce += 1;
-            self.set_nonce(nonce);
-            // println!("{} {} {} bits {}", nonce, &self.hash.as_ref().unwrap().clone(), get_bits_from_hash(self.hash.as_ref().unwrap().clone()), format!("{:2x}", get_bits_from_hash(self.hash.as_ref().unwrap().clone())));
+    pub fn mine(&mut self, target: Target) -> Result<(), BlockError> {
+        self.mine_with_max_nonce(target, MAX_NONCE)
+    }
+
+    // Once the nonce range is exhausted the extra nonce changes the midstate.
+    pub(crate) fn mine_with_max_nonce(&mut self, target: Target, max_nonce: u128) -> Result<(), BlockError> {
+        self.header.bits = target.to_compact();
+        let target = match self.header.target() {
+            Some(target) if !target.is_zero() => target,
+            _ => return Err(BlockError::InvalidTarget { bits: self.header.bits }),
+        };
+        loop {
+            let hasher = HeaderHasher::new(&self.header);
+            if let Some(nonce) = (1..=max_nonce).find(|nonce| hasher.meets_target(*nonce, &target)) {
+                self.header.nonce = nonce;
+                break;
+            }
+            self.header.extra_nonce = self.header.extra_nonce.wrapping_add(1);
         }
-        println!("GOT IT {} {}", nonce, &self.hash.as_ref().unwrap().clone());
+        self.update_hash();
+        Ok(())
     }
 }
 
 impl Hashable for Block {
     fn hash(&self) -> Hash {
-        let mut hasher = Blake2s::new();
-        hasher.update(format!("{:?}", (self.prev_hash.clone(), self.nonce)).as_bytes());
-        for tx in self.transactions.iter() {
-            hasher.update(tx.hash())
-        }
+        self.header.hash()
+    }
+}
 
-        hex::encode(hasher.finalize_fixed())
+impl Encode for Block {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.header.encode(out);
+        self.transactions.encode(out);
+    }
+}
+
+impl Decode for Block {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        let header = BlockHeader::decode(reader)?;
+        let transactions = Vec::<Transaction>::decode(reader)?;
+        Ok(Self {
+            hash: Some(header.hash()),
+            header,
+            transactions,
+        })
     }
 }
 
@@ -71,7 +165,7 @@ impl Hashable for Block {
 mod tests {
     use ed25519_dalek::Keypair;
 
-    use crate::{types::{Blockchain, TransactionData}, utils::{create_account_tx, generate_account_id, mint_initial_supply}};
+    use crate::{types::{Blockchain, TransactionData, U256, MAX_TARGET}, utils::{create_account_tx, generate_account_id, mint_initial_supply, new_block}};
 
     use super::*;
 
@@ -82,7 +176,7 @@ mod tests {
         let tx = Transaction::new(
             TransactionData::CreateAccount(
                 "alice".to_string(),
-                keypair_account.public.as_bytes().clone(),
+                *keypair_account.public.as_bytes(),
             ),
             None,
         );
@@ -100,7 +194,7 @@ mod tests {
         let tx = Transaction::new(
             TransactionData::CreateAccount(
                 "alice".to_string(),
-                keypair_account.public.as_bytes().clone(),
+                *keypair_account.public.as_bytes(),
             ),
             None,
         );
@@ -115,6 +209,72 @@ mod tests {
         assert_ne!(hash1, hash2);
     }
 
+    #[test]
+    fn test_header_hash() {
+        let mut block = Block::new(None);
+        let (_, tx) = create_account_tx("alice".to_string());
+        block.add_transaction(tx);
+        block.set_nonce(1);
+
+        assert!(block.verify());
+        assert_eq!(block.hash, Some(block.header().hash()));
+
+        let proof = block.merkle_proof(0).unwrap();
+        assert!(proof.verify(&block.transactions()[0], block.header().merkle_root()));
+
+        let mut header = block.header().clone();
+        header.timestamp += 1;
+        assert_ne!(header.hash(), block.hash());
+
+        let mut tampered = block.clone();
+        tampered.header.timestamp += 1;
+        assert!(!tampered.verify());
+
+        let mut tampered = block.clone();
+        tampered.transactions[0] = create_account_tx("bob".to_string()).1;
+        assert!(!tampered.verify());
+    }
+
+    #[test]
+    fn test_encoding() {
+        let mut block = Block::new(Some("00".repeat(32)));
+        block.add_transaction(create_account_tx("alice".to_string()).1);
+        block.add_coinbase("alice".to_string(), 50);
+        block.set_nonce(3);
+
+        let decoded = Block::from_bytes(&block.to_bytes()).unwrap();
+        assert!(decoded.verify());
+        assert_eq!(decoded, block);
+
+        let header = BlockHeader {
+            version: 1,
+            prev_hash: None,
+            merkle_root: "ab".to_string(),
+            state_root: "cd".to_string(),
+            timestamp: 1_600_000_000,
+            bits: 0x1effffff,
+            extra_nonce: 7,
+            nonce: 42,
+        };
+        assert_eq!(
+            hex::encode(header.to_bytes()),
+            "01".to_string()
+                + "00000001"
+                + "00"
+                + "00000002" + "6162"
+                + "00000002" + "6364"
+                + "000000005f5e1000"
+                + "1effffff"
+                + "0000000000000007"
+                + "0000000000000000000000000000002a"
+        );
+        assert_eq!(
+            header.hash(),
+            "e631140e1c2637af7a160b99295133b1f6b00875f4c81c96843e876088cc9875"
+        );
+        assert_eq!(BlockHeader::from_bytes(&header.to_bytes()), Ok(header));
+    }
+
     #[test]
     fn test_mining() {
         let mut bc = Blockchain::new();
@@ -123,19 +283,21 @@ mod tests {
         let (_, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
         let tx_mint_initial_supply = mint_initial_supply(account_id_satoshi.clone(), 100_000_000);
 
-        let mut block = Block::new(bc.get_last_block_hash());
+        let mut block = new_block(&bc);
         block.add_transaction(tx_create_satoshi);
         block.add_transaction(tx_mint_initial_supply);
-        block.mine(bc.target.clone());
+        block.set_state_root(bc.state_root_after(&block).unwrap());
+        block.mine(bc.target).unwrap();
         assert!(bc.append_block(block).is_ok());
 
         let mut count = 0;
         loop {
             count += 1;
-            let mut block = Block::new(bc.get_last_block_hash());
+            let mut block = new_block(&bc);
             let (_, tx_create_alice) = create_account_tx(generate_account_id());
             block.add_transaction(tx_create_alice);
-            block.mine(bc.target.clone());
+            block.set_state_root(bc.state_root_after(&block).unwrap());
+            block.mine(bc.target).unwrap();
             assert!(bc.append_block(block).is_ok());
             if count == 10 {
                 break;
@@ -143,4 +305,33 @@ mod tests {
         }
 
     }
+
+    #[test]
+    fn test_work() {
+        let mut block = Block::new(None);
+        block.set_bits(MAX_TARGET);
+        assert_eq!(block.header().work(), U256::from_u64(0x10001));
+
+        block.set_bits(0x1d00ffff);
+        assert_eq!(block.header().work(), U256::from_u64(0x0100010001));
+
+        block.set_bits(0x21010000);
+        assert_eq!(block.header().target(), None);
+        assert_eq!(block.header().work(), U256::ZERO);
+        assert!(!block.header().meets_target());
+    }
+
+    #[test]
+    fn test_mine_extra_nonce() {
+        let mut block = Block::new(None);
+        block.add_transaction(create_account_tx(generate_account_id()).1);
+        let target = U256::from_compact(MAX_TARGET).unwrap();
+        block.mine_with_max_nonce(target, 4).unwrap();
+        assert!(block.header().nonce() <= 4);
+        assert!(block.header().extra_nonce() > 0);
+        assert!(block.header().meets_target());
+        assert!(block.verify());
+
+        assert_eq!(block.mine(U256::ZERO), Err(BlockError::InvalidTarget { bits: 0 }));
+    }
 }
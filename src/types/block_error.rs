@@ -0,0 +1,146 @@
+use std::fmt;
+
+use crate::types::{AccountId, Balance, Bits, Hash, StorageError, Timestamp, TxError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockError {
+    InvalidHash,
+    Empty,
+    TooManyTransactions { count: usize, max: usize },
+    AlreadyKnown,
+    UnknownParent,
+    GenesisExists,
+    InvalidBeneficiary { account_id: AccountId },
+    InvalidBits { expected: Bits, got: Bits },
+    InvalidTarget { bits: Bits },
+    InsufficientWork,
+    TimestampBeforeMedian {
+        timestamp: Timestamp,
+        median_time_past: Timestamp,
+    },
+    TimestampTooFarInFuture {
+        timestamp: Timestamp,
+        max_timestamp: Timestamp,
+    },
+    MisplacedCoinbase { index: usize },
+    InvalidCoinbaseAmount { expected: Balance, got: Balance },
+    CoinbaseOverflow,
+    ExceedsMaxSupply,
+    NonceOrder { index: usize, account_id: AccountId },
+    Transaction { index: usize, error: TxError },
+    StateRootMismatch { expected: Hash, got: Hash },
+    Storage(StorageError),
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::InvalidHash => write!(f, "Block has invalid hash."),
+            BlockError::Empty => write!(f, "Block has 0 transactions."),
+            BlockError::TooManyTransactions { count, max } => write!(
+                f,
+                "Block has too many transactions: {} of at most {}.",
+                count, max
+            ),
+            BlockError::AlreadyKnown => write!(f, "Block already known."),
+            BlockError::UnknownParent => write!(f, "Unknown parent block."),
+            BlockError::GenesisExists => write!(f, "Genesis block already exists."),
+            BlockError::InvalidBeneficiary { account_id } => {
+                write!(f, "Invalid beneficiary account {}.", account_id)
+            }
+            BlockError::InvalidBits { expected, got } => write!(
+                f,
+                "Block has invalid target bits: expected {:x}, got {:x}.",
+                expected, got
+            ),
+            BlockError::InvalidTarget { bits } => write!(f, "Block target bits {:x} are not a valid target.", bits),
+            BlockError::InsufficientWork => write!(f, "Hash greater than target."),
+            BlockError::TimestampBeforeMedian {
+                timestamp,
+                median_time_past,
+            } => write!(
+                f,
+                "Block timestamp {} is not later than median time past {}.",
+                timestamp, median_time_past
+            ),
+            BlockError::TimestampTooFarInFuture {
+                timestamp,
+                max_timestamp,
+            } => write!(
+                f,
+                "Block timestamp {} is too far in the future, max allowed {}.",
+                timestamp, max_timestamp
+            ),
+            BlockError::MisplacedCoinbase { index } => write!(
+                f,
+                "Coinbase transaction must be the first in block, found at {}.",
+                index
+            ),
+            BlockError::InvalidCoinbaseAmount { expected, got } => write!(
+                f,
+                "Invalid coinbase amount: expected {}, got {}.",
+                expected, got
+            ),
+            BlockError::CoinbaseOverflow => write!(f, "Coinbase amount overflow."),
+            BlockError::ExceedsMaxSupply => write!(f, "Block exceeds max supply."),
+            BlockError::NonceOrder { index, account_id } => write!(
+                f,
+                "Transaction {} nonce out of order for {}.",
+                index, account_id
+            ),
+            BlockError::Transaction { index, error } => {
+                write!(f, "Error during tx {} execution: {}", index, error)
+            }
+            BlockError::StateRootMismatch { expected, got } => write!(
+                f,
+                "Block has invalid state root: expected {}, got {}.",
+                expected, got
+            ),
+            BlockError::Storage(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for BlockError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BlockError::Transaction { error, .. } => Some(error),
+            BlockError::Storage(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<StorageError> for BlockError {
+    fn from(error: StorageError) -> Self {
+        BlockError::Storage(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let error = BlockError::Transaction {
+            index: 2,
+            error: TxError::InsufficientBalance {
+                account_id: "alice".to_string(),
+                required: 10,
+                available: 3,
+            },
+        };
+        assert_eq!(
+            error.to_string(),
+            "Error during tx 2 execution: Account alice doesn't have enough currency: required 10, available 3."
+        );
+        assert_eq!(
+            error.source().unwrap().to_string(),
+            "Account alice doesn't have enough currency: required 10, available 3."
+        );
+        assert!(BlockError::InvalidHash.source().is_none());
+    }
+}
@@ -0,0 +1,102 @@
+use crate::encoding::Reader;
+use crate::traits::{Decode, Encode, Hashable};
+use crate::types::{Bits, Error, Hash, HeaderHasher, Target, Timestamp, U256, Work};
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct BlockHeader {
+    pub(crate) version: u32,
+    pub(crate) prev_hash: Option<Hash>,
+    pub(crate) merkle_root: Hash,
+    pub(crate) state_root: Hash,
+    pub(crate) timestamp: Timestamp,
+    pub(crate) bits: Bits,
+    pub(crate) extra_nonce: u64,
+    pub(crate) nonce: u128,
+}
+
+impl BlockHeader {
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn prev_hash(&self) -> Option<&Hash> {
+        self.prev_hash.as_ref()
+    }
+
+    pub fn merkle_root(&self) -> &Hash {
+        &self.merkle_root
+    }
+
+    pub fn state_root(&self) -> &Hash {
+        &self.state_root
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    pub fn bits(&self) -> Bits {
+        self.bits
+    }
+
+    pub fn extra_nonce(&self) -> u64 {
+        self.extra_nonce
+    }
+
+    pub fn nonce(&self) -> u128 {
+        self.nonce
+    }
+
+    pub fn meets_target(&self) -> bool {
+        match self.target() {
+            Some(target) => HeaderHasher::new(self).meets_target(self.nonce, &target),
+            None => false,
+        }
+    }
+
+    pub fn target(&self) -> Option<Target> {
+        U256::from_compact(self.bits)
+    }
+
+    pub fn work(&self) -> Work {
+        self.target().map(|target| target.work()).unwrap_or(U256::ZERO)
+    }
+
+    pub(crate) fn encode_without_nonce(&self, out: &mut Vec<u8>) {
+        self.version.encode(out);
+        self.prev_hash.encode(out);
+        self.merkle_root.encode(out);
+        self.state_root.encode(out);
+        self.timestamp.encode(out);
+        self.bits.encode(out);
+        self.extra_nonce.encode(out);
+    }
+}
+
+impl Hashable for BlockHeader {
+    fn hash(&self) -> Hash {
+        hex::encode(HeaderHasher::new(self).hash(self.nonce))
+    }
+}
+
+impl Encode for BlockHeader {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.encode_without_nonce(out);
+        self.nonce.encode(out);
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(Self {
+            version: u32::decode(reader)?,
+            prev_hash: Option::<Hash>::decode(reader)?,
+            merkle_root: Hash::decode(reader)?,
+            state_root: Hash::decode(reader)?,
+            timestamp: Timestamp::decode(reader)?,
+            bits: Bits::decode(reader)?,
+            extra_nonce: u64::decode(reader)?,
+            nonce: u128::decode(reader)?,
+        })
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::traits::Hashable;
+use crate::types::{Block, BlockError, Hash, U256, Work};
+
+#[derive(Debug)]
+struct TreeEntry {
+    block: Block,
+    height: usize,
+    work: Work,
+}
+
+#[derive(Default, Debug)]
+pub struct BlockTree {
+    entries: HashMap<Hash, TreeEntry>,
+    active: Vec<Hash>,
+}
+
+impl BlockTree {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.active.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &Hash) -> Option<&Block> {
+        self.entries.get(hash).map(|entry| &entry.block)
+    }
+
+    pub fn height(&self, hash: &Hash) -> Option<usize> {
+        self.entries.get(hash).map(|entry| entry.height)
+    }
+
+    pub fn work(&self, hash: &Hash) -> Option<Work> {
+        self.entries.get(hash).map(|entry| entry.work)
+    }
+
+    pub fn tip(&self) -> Option<&Block> {
+        self.active.last().and_then(|hash| self.get(hash))
+    }
+
+    pub fn tip_hash(&self) -> Option<&Hash> {
+        self.active.last()
+    }
+
+    pub fn tip_work(&self) -> Work {
+        self.active
+            .last()
+            .and_then(|hash| self.work(hash))
+            .unwrap_or(U256::ZERO)
+    }
+
+    pub fn get_by_height(&self, height: usize) -> Option<&Block> {
+        self.active.get(height).and_then(|hash| self.get(hash))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn get_by_height_mut(&mut self, height: usize) -> Option<&mut Block> {
+        let hash = self.active.get(height)?;
+        self.entries.get_mut(hash).map(|entry| &mut entry.block)
+    }
+
+    pub fn is_active(&self, hash: &Hash) -> bool {
+        match self.entries.get(hash) {
+            Some(entry) => self.active.get(entry.height) == Some(hash),
+            None => false,
+        }
+    }
+
+    // Up to `count` blocks ending at `hash` on whichever branch it is on,
+    // oldest first.
+    pub fn ancestors(&self, hash: &Hash, count: usize) -> Vec<&Block> {
+        let mut ancestors = vec![];
+        let mut next = self.get(hash);
+        while let Some(block) = next.filter(|_| ancestors.len() < count) {
+            ancestors.push(block);
+            next = block.prev_hash().and_then(|prev_hash| self.get(prev_hash));
+        }
+        ancestors.reverse();
+        ancestors
+    }
+
+    pub fn side_block_count(&self) -> usize {
+        self.entries.len() - self.active.len()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Block> + '_ {
+        self.active.iter().rev().map(move |hash| &self.entries[hash].block)
+    }
+
+    pub fn insert(&mut self, block: Block) -> Result<Hash, BlockError> {
+        let hash = block.hash();
+        if self.entries.contains_key(&hash) {
+            return Err(BlockError::AlreadyKnown);
+        }
+
+        let (height, parent_work) = match block.prev_hash() {
+            Some(prev_hash) => {
+                let parent = self
+                    .entries
+                    .get(prev_hash)
+                    .ok_or(BlockError::UnknownParent)?;
+                (parent.height + 1, parent.work)
+            }
+            None if self.entries.is_empty() => (0, U256::ZERO),
+            None => return Err(BlockError::GenesisExists),
+        };
+
+        let work = parent_work.saturating_add(block.header().work());
+        self.entries.insert(hash.clone(), TreeEntry { block, height, work });
+        Ok(hash)
+    }
+
+    pub(crate) fn remove(&mut self, hash: &Hash) -> Option<Block> {
+        if self.is_active(hash) {
+            return None;
+        }
+        self.entries.remove(hash).map(|entry| entry.block)
+    }
+
+    // Drops side branch leaves with the least work until at most `max` blocks
+    // are kept off the active chain.
+    pub(crate) fn prune_side_blocks(&mut self, max: usize) {
+        while self.side_block_count() > max {
+            let parents: HashSet<&Hash> = self
+                .entries
+                .values()
+                .filter_map(|entry| entry.block.prev_hash())
+                .collect();
+            let leaf = self
+                .entries
+                .iter()
+                .filter(|(hash, _)| !parents.contains(hash) && !self.is_active(hash))
+                .min_by_key(|(_, entry)| entry.work)
+                .map(|(hash, _)| hash.clone());
+            match leaf {
+                Some(leaf) => self.entries.remove(&leaf),
+                None => break,
+            };
+        }
+    }
+
+    pub(crate) fn push_active(&mut self, hash: Hash) {
+        self.active.push(hash);
+    }
+
+    pub(crate) fn pop_active(&mut self) -> Option<Hash> {
+        self.active.pop()
+    }
+
+    // Hashes from the block after the fork point with the active chain up to
+    // and including `hash`, along with the fork point height.
+    pub fn branch(&self, hash: &Hash) -> Option<(usize, Vec<Hash>)> {
+        let mut branch = vec![];
+        let mut current = hash;
+        while !self.is_active(current) {
+            branch.push(current.clone());
+            current = self.entries.get(current)?.block.prev_hash()?;
+        }
+
+        branch.reverse();
+        Some((self.entries[current].height, branch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::MAX_TARGET;
+    use crate::utils::{create_account_tx, generate_account_id};
+
+    use super::*;
+
+    fn block(prev_hash: Option<Hash>) -> Block {
+        let mut block = Block::new(prev_hash);
+        block.set_bits(MAX_TARGET);
+        block.add_transaction(create_account_tx(generate_account_id()).1);
+        block
+    }
+
+    #[test]
+    fn test_branches() {
+        let mut tree = BlockTree::new();
+        let genesis = tree.insert(block(None)).unwrap();
+        tree.push_active(genesis.clone());
+
+        let a1 = tree.insert(block(Some(genesis.clone()))).unwrap();
+        tree.push_active(a1.clone());
+        let b1 = tree.insert(block(Some(genesis.clone()))).unwrap();
+        let b2 = tree.insert(block(Some(b1.clone()))).unwrap();
+
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.block_count(), 4);
+        assert_eq!(tree.height(&b2), Some(2));
+        assert!(tree.work(&b2).unwrap() > tree.tip_work());
+        assert!(tree.is_active(&a1));
+        assert!(!tree.is_active(&b1));
+        assert_eq!(tree.branch(&b2), Some((0, vec![b1.clone(), b2.clone()])));
+        assert_eq!(tree.branch(&a1), Some((1, vec![])));
+
+        assert_eq!(
+            tree.insert(block(Some("unknown".to_string()))).err().unwrap(),
+            BlockError::UnknownParent
+        );
+        assert_eq!(
+            tree.insert(block(None)).err().unwrap(),
+            BlockError::GenesisExists
+        );
+        let known = tree.get(&b1).unwrap().clone();
+        assert_eq!(tree.insert(known).err().unwrap(), BlockError::AlreadyKnown);
+
+        assert_eq!(tree.side_block_count(), 2);
+        let ancestors: Vec<Hash> = tree.ancestors(&b2, 5).iter().map(|block| block.hash()).collect();
+        assert_eq!(ancestors, vec![genesis.clone(), b1.clone(), b2.clone()]);
+        assert_eq!(tree.ancestors(&b2, 1).len(), 1);
+
+        assert!(tree.remove(&a1).is_none());
+        assert!(tree.remove(&b2).is_some());
+        assert!(!tree.contains(&b2));
+    }
+
+    #[test]
+    fn test_prune_side_blocks() {
+        let mut tree = BlockTree::new();
+        let genesis = tree.insert(block(None)).unwrap();
+        tree.push_active(genesis.clone());
+        let a1 = tree.insert(block(Some(genesis.clone()))).unwrap();
+        tree.push_active(a1.clone());
+
+        let b1 = tree.insert(block(Some(genesis.clone()))).unwrap();
+        let c2 = tree.insert(block(Some(a1.clone()))).unwrap();
+        let c3 = tree.insert(block(Some(c2.clone()))).unwrap();
+
+        tree.prune_side_blocks(3);
+        assert_eq!(tree.side_block_count(), 3);
+
+        tree.prune_side_blocks(2);
+        assert!(!tree.contains(&b1) && tree.contains(&c3));
+
+        tree.prune_side_blocks(1);
+        assert!(tree.contains(&c2) && !tree.contains(&c3));
+
+        tree.prune_side_blocks(0);
+        assert_eq!(tree.block_count(), 2);
+        assert!(tree.is_active(&a1));
+    }
+}
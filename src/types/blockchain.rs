@@ -1,37 +1,85 @@
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
+use crate::state_tree::{AccountProof, StateTree};
+use crate::storage::BlockStore;
 use crate::traits::{Hashable, WorldState};
-use crate::types::{Account, AccountId, AccountType, Bits, Block, Chain, Difficulty, EXPECTED_TIME, Error, Hash, MAX_TARGET, PublicKeyBytes, Target, Timestamp, Transaction};
-use crate::utils::{get_bits_from_hash, get_timestamp};
+use crate::types::{Account, AccountId, Balance, Block, BlockError, BlockHeader, BlockTree, ChainParams, ChainUpdate, Difficulty, Hash, MEDIAN_TIME_SPAN, PoolError, StorageError, Target, Timestamp, Transaction, TransactionData, TransactionPool, TxError, U256, ValidationError};
+use crate::utils::get_timestamp;
+
+#[derive(Debug, Clone)]
+struct BlockUndo {
+    accounts: Vec<(AccountId, Option<Account>)>,
+    locked_rewards: VecDeque<(usize, AccountId, Balance)>,
+    issued_supply: Balance,
+    target: Target,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChainSnapshot {
+    params: ChainParams,
+    blocks: Vec<Block>,
+    accounts: HashMap<AccountId, Account>,
+}
+
+impl ChainSnapshot {
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn validate_full(&self) -> Result<(), ValidationError> {
+        Blockchain::replay(&self.params, self.blocks.iter(), &self.accounts)
+    }
+}
 
 #[derive(Default, Debug)]
 pub struct Blockchain {
-    blocks: Chain<Block>,
+    blocks: BlockTree,
+    undo: Vec<BlockUndo>,
     accounts: HashMap<AccountId, Account>,
-    transaction_pool: Vec<Transaction>,
+    state: StateTree,
+    locked_rewards: VecDeque<(usize, AccountId, Balance)>,
+    issued_supply: Balance,
+    transaction_pool: TransactionPool,
+    params: ChainParams,
     pub(crate) target: Target,
-    difficulty: Difficulty,
-    first_block_timestamp: Timestamp,
-    last_block_timestamp: Timestamp,
+    store: Option<BlockStore>,
 }
 
-impl WorldState for Blockchain {
-    fn create_account(
-        &mut self,
-        account_id: AccountId,
-        account_type: AccountType,
-        public_key: PublicKeyBytes,
-    ) -> Result<(), Error> {
-        match self.accounts.entry(account_id.clone()) {
-            Entry::Occupied(_) => Err(format!("AccountId already exist: {}", account_id)),
+// A chain shared between the miner, the RPC server and the P2P layer.
+pub type SharedChain = Arc<Mutex<Blockchain>>;
+
+impl WorldState for HashMap<AccountId, Account> {
+    fn create_account(&mut self, account_id: AccountId, account: Account) -> Result<(), TxError> {
+        match self.entry(account_id.clone()) {
+            Entry::Occupied(_) => Err(TxError::AccountExists { account_id }),
             Entry::Vacant(v) => {
-                v.insert(Account::new(account_type, public_key));
+                v.insert(account);
                 Ok(())
             }
         }
     }
 
+    fn get_account_by_id(&self, account_id: AccountId) -> Option<&Account> {
+        self.get(&account_id)
+    }
+
+    fn get_account_by_id_mut(&mut self, account_id: AccountId) -> Option<&mut Account> {
+        self.get_mut(&account_id)
+    }
+}
+
+impl WorldState for Blockchain {
+    fn create_account(&mut self, account_id: AccountId, account: Account) -> Result<(), TxError> {
+        self.accounts.create_account(account_id, account)
+    }
+
     fn get_account_by_id(&self, account_id: AccountId) -> Option<&Account> {
         self.accounts.get(&account_id)
     }
@@ -43,56 +91,536 @@ impl WorldState for Blockchain {
 
 impl Blockchain {
     pub fn new() -> Self {
+        Self::with_params(ChainParams::default())
+    }
+
+    pub fn with_params(params: ChainParams) -> Self {
         Self {
-            target: format!("{:x}", MAX_TARGET),
-            difficulty: 0xffff as f32,
+            transaction_pool: TransactionPool::new(
+                params.max_pool_size,
+                params.max_pool_txs_per_sender,
+            ),
+            target: params.difficulty_algorithm.max_target(),
+            params,
             ..Default::default()
         }
     }
 
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        Self::open_with_params(path, ChainParams::default())
+    }
+
+    pub fn open_with_params(path: &Path, params: ChainParams) -> Result<Self, StorageError> {
+        let (store, blocks) = BlockStore::open(path)?;
+        let mut blockchain = Self::with_params(params);
+        for (position, block) in blocks.into_iter().enumerate() {
+            blockchain
+                .append_block(block)
+                .map_err(|error| StorageError::InvalidBlock {
+                    position,
+                    error: Box::new(error),
+                })?;
+        }
+        blockchain.store = Some(store);
+        Ok(blockchain)
+    }
+
+    pub fn params(&self) -> &ChainParams {
+        &self.params
+    }
+
     pub fn len(&self) -> usize {
         self.blocks.len()
     }
 
-    pub fn append_block(&mut self, block: Block) -> Result<(), Error> {
-        //TODO Task 3: Implement mining
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn issued_supply(&self) -> Balance {
+        self.issued_supply
+    }
+
+    pub fn next_block_subsidy(&self) -> Balance {
+        self.params
+            .block_subsidy(self.blocks.len())
+            .min(self.params.max_supply.saturating_sub(self.issued_supply))
+    }
+
+    pub fn transaction_pool(&self) -> &TransactionPool {
+        &self.transaction_pool
+    }
+
+    pub fn submit_transaction(&mut self, tx: Transaction) -> Result<(), PoolError> {
+        if self.transaction_pool.contains(&tx.hash()) {
+            return Err(PoolError::AlreadyInPool);
+        }
+
+        match &tx.data {
+            TransactionData::CreateAccount(account_id, _)
+            | TransactionData::CreateMultisigAccount { account_id, .. } => {
+                if self.accounts.contains_key(account_id) {
+                    return Err(TxError::AccountExists {
+                        account_id: account_id.clone(),
+                    }
+                    .into());
+                }
+            }
+            TransactionData::Transfer { to, amount, fee } => {
+                let from = tx.from.as_ref().ok_or(TxError::MissingSender)?;
+                if from == to {
+                    return Err(TxError::SelfTransfer.into());
+                }
+
+                let mut pending_total = amount.checked_add(*fee).ok_or(TxError::AmountOverflow)?;
+                for pending in self.transaction_pool.pending_from(from) {
+                    if pending.nonce == tx.nonce {
+                        return Err(PoolError::NoncePending {
+                            account_id: from.clone(),
+                            nonce: tx.nonce,
+                        });
+                    }
+                    if let TransactionData::Transfer { amount, fee, .. } = &pending.data {
+                        pending_total = pending_total.saturating_add(amount.saturating_add(*fee));
+                    }
+                }
+
+                let sender = self.accounts.get(from).ok_or_else(|| TxError::InvalidSender {
+                    account_id: from.clone(),
+                })?;
+                if !self.accounts.contains_key(to) {
+                    return Err(TxError::InvalidReceiver { account_id: to.clone() }.into());
+                }
+                if tx.nonce < sender.nonce {
+                    return Err(TxError::NonceUsed {
+                        expected: sender.nonce,
+                        got: tx.nonce,
+                    }
+                    .into());
+                }
+                if !tx.verify(sender) {
+                    return Err(TxError::InvalidSignature.into());
+                }
+                let available = sender.balance - sender.locked;
+                if available < pending_total {
+                    return Err(TxError::InsufficientBalance {
+                        account_id: from.clone(),
+                        required: pending_total,
+                        available,
+                    }
+                    .into());
+                }
+            }
+            _ => return Err(PoolError::NotPoolable),
+        }
+
+        self.transaction_pool.insert(tx)
+    }
+
+    pub fn build_block_template(&mut self, beneficiary: AccountId) -> Result<Block, BlockError> {
+        if !self.accounts.contains_key(&beneficiary) {
+            return Err(BlockError::InvalidBeneficiary { account_id: beneficiary });
+        }
+
+        let is_genesis = self.blocks.is_empty();
+        let mut scratch = self.accounts.clone();
+        let mut candidates: Vec<&Transaction> = self.transaction_pool.iter().collect();
+        let mut block = Block::new(self.get_last_block_hash());
+
+        while block.transactions.len() + 1 < self.params.max_block_transactions {
+            let next = candidates.iter().position(|tx| match &tx.from {
+                Some(from) => !matches!(scratch.get(from), Some(sender) if tx.nonce > sender.nonce),
+                None => true,
+            });
+            let tx = match next {
+                Some(index) => candidates.remove(index),
+                None => break,
+            };
+
+            if tx.execute(&mut scratch, is_genesis).is_ok() {
+                block.add_transaction(tx.clone());
+            }
+        }
+
+        block.set_timestamp(block.timestamp().max(self.median_time_past() + 1));
+        block.add_coinbase(beneficiary, self.next_block_subsidy());
+        block.set_state_root(self.state_root_after(&block)?);
+        Ok(block)
+    }
+
+    pub fn blocks(&self) -> &BlockTree {
+        &self.blocks
+    }
 
+    pub fn append_block(&mut self, block: Block) -> Result<ChainUpdate, BlockError> {
         if !block.verify() {
-            return Err("Block has invalid hash".to_string());
+            return Err(BlockError::InvalidHash);
         }
-        let is_genesis = self.blocks.len() == 0;
 
-        if block.transactions.len() == 0 {
-            return Err("Block has 0 transactions.".to_string());
+        if block.transactions.is_empty() {
+            return Err(BlockError::Empty);
         }
 
-        let account_backup = self.accounts.clone();
-        for tx in &block.transactions {
+        if block.transactions.len() > self.params.max_block_transactions {
+            return Err(BlockError::TooManyTransactions {
+                count: block.transactions.len(),
+                max: self.params.max_block_transactions,
+            });
+        }
+
+        let max_timestamp = get_timestamp().saturating_add(self.params.max_future_block_time);
+        if block.timestamp() > max_timestamp {
+            return Err(BlockError::TimestampTooFarInFuture {
+                timestamp: block.timestamp(),
+                max_timestamp,
+            });
+        }
+
+        if self.blocks.contains(&block.hash()) {
+            return Err(BlockError::AlreadyKnown);
+        }
+
+        if block.prev_hash() == self.blocks.tip_hash() {
+            let hash = self.blocks.insert(block)?;
+            if let Err(error) = self.connect_block(&hash) {
+                self.blocks.remove(&hash);
+                return Err(error);
+            }
+            return Ok(ChainUpdate::Extended);
+        }
+
+        // Side branch blocks are only fully validated on a reorg, but their
+        // headers are checked against their own branch before being stored.
+        if let Some(prev_hash) = block.prev_hash() {
+            if !self.blocks.contains(prev_hash) {
+                return Err(BlockError::UnknownParent);
+            }
+            Self::check_header(
+                &block.header,
+                self.target_after(prev_hash),
+                self.median_time_past_after(prev_hash),
+            )?;
+        }
+
+        let hash = self.blocks.insert(block)?;
+        if self.blocks.work(&hash) <= Some(self.blocks.tip_work()) {
+            self.blocks.prune_side_blocks(self.params.max_side_blocks);
+            return Ok(ChainUpdate::SideBranch);
+        }
+        self.reorg(&hash)
+    }
+
+    fn reorg(&mut self, tip: &Hash) -> Result<ChainUpdate, BlockError> {
+        let (fork_height, branch) = self.blocks.branch(tip).ok_or(BlockError::UnknownParent)?;
+
+        let mut disconnected = vec![];
+        while self.blocks.len() > fork_height + 1 {
+            disconnected.push(self.disconnect_tip());
+        }
+
+        let mut connected = vec![];
+        for (index, hash) in branch.iter().enumerate() {
+            if let Err(error) = self.connect_block(hash) {
+                for _ in 0..connected.len() {
+                    self.disconnect_tip();
+                }
+                for hash in &branch[index..] {
+                    self.blocks.remove(hash);
+                }
+                for (block, redo) in disconnected.into_iter().rev() {
+                    self.reconnect_block(&block, redo);
+                }
+                return Err(error);
+            }
+            connected.push(self.blocks.get(hash).unwrap().clone());
+        }
+
+        let disconnected: Vec<Block> = disconnected.into_iter().map(|(block, _)| block).collect();
+        self.blocks.prune_side_blocks(self.params.max_side_blocks);
+        for block in &disconnected {
+            for tx in block.transactions.iter().filter(|tx| !tx.is_coinbase()) {
+                let _ = self.submit_transaction(tx.clone());
+            }
+        }
+
+        Ok(ChainUpdate::Reorg {
+            disconnected,
+            connected,
+        })
+    }
+
+    fn connect_block(&mut self, hash: &Hash) -> Result<(), BlockError> {
+        let block = self.blocks.get(hash).cloned().ok_or(BlockError::UnknownParent)?;
+
+        if !self.blocks.is_empty() {
+            Self::check_header(&block.header, self.target, self.median_time_past())?;
+        }
+
+        let undo = self.apply_block(&block)?;
+        let state_root = self.state_root();
+        if &state_root != block.header.state_root() {
+            self.restore(undo);
+            return Err(BlockError::StateRootMismatch {
+                expected: state_root,
+                got: block.header.state_root().clone(),
+            });
+        }
+
+        if let Some(store) = &mut self.store {
+            if !store.contains(hash) {
+                if let Err(error) = store.append(&block, self.blocks.len()) {
+                    self.restore(undo);
+                    return Err(error.into());
+                }
+            }
+        }
+
+        self.update_transaction_pool(&block);
+        self.blocks.push_active(hash.clone());
+        self.undo.push(undo);
+        self.target = self.next_target();
+        Ok(())
+    }
+
+    fn check_header(header: &BlockHeader, target: Target, median_time_past: Timestamp) -> Result<(), BlockError> {
+        let bits = target.to_compact();
+        if header.bits != bits {
+            return Err(BlockError::InvalidBits {
+                expected: bits,
+                got: header.bits,
+            });
+        }
+        if !header.meets_target() {
+            return Err(BlockError::InsufficientWork);
+        }
+        if header.timestamp <= median_time_past {
+            return Err(BlockError::TimestampBeforeMedian {
+                timestamp: header.timestamp,
+                median_time_past,
+            });
+        }
+        Ok(())
+    }
+
+    fn apply_block(&mut self, block: &Block) -> Result<BlockUndo, BlockError> {
+        let is_genesis = self.blocks.is_empty();
+        let subsidy = self.check_coinbase(block)?;
+        let issued_supply = self.check_supply(block, subsidy)?;
+        Self::check_nonce_order(block)?;
+
+        let height = self.blocks.len();
+        let undo = self.block_undo(block);
+        for (index, tx) in block.transactions.iter().enumerate() {
             let res = tx.execute(self, is_genesis);
             if let Err(error) = res {
-                self.accounts = account_backup;
-                return Err(format!("Error during tx execution: {}", error));
+                self.restore(undo);
+                return Err(BlockError::Transaction { index, error });
+            }
+            if let TransactionData::Coinbase { to, amount } = &tx.data {
+                self.lock_reward(height, to.clone(), *amount);
+            }
+        }
+
+        self.issued_supply = issued_supply;
+        self.release_rewards(height + 1);
+        // The undo record lists every account the block could have changed.
+        for (account_id, _) in &undo.accounts {
+            self.state.update(account_id, self.accounts.get(account_id));
+        }
+        Ok(undo)
+    }
+
+    pub fn state_root(&self) -> Hash {
+        self.state.root()
+    }
+
+    // Proof against the current state, which the tip header commits to.
+    pub fn prove_account(&self, account_id: AccountId) -> AccountProof {
+        self.state.prove(&account_id, self.accounts.get(&account_id))
+    }
+
+    // State root the accounts would have after connecting `block` on top of
+    // the current tip, which is what a producer has to commit to in its header.
+    pub fn state_root_after(&mut self, block: &Block) -> Result<Hash, BlockError> {
+        if block.prev_hash() != self.blocks.tip_hash() {
+            return Err(BlockError::UnknownParent);
+        }
+        let undo = self.apply_block(block)?;
+        let state_root = self.state_root();
+        self.restore(undo);
+        Ok(state_root)
+    }
+
+    // Returns the disconnected block along with the state it left behind, so
+    // a failed reorg can put it back without validating it again.
+    fn disconnect_tip(&mut self) -> (Block, BlockUndo) {
+        let hash = self.blocks.pop_active().unwrap();
+        let undo = self.undo.pop().unwrap();
+        let redo = self.counterpart(&undo);
+        self.restore(undo);
+        (self.blocks.get(&hash).unwrap().clone(), redo)
+    }
+
+    fn reconnect_block(&mut self, block: &Block, redo: BlockUndo) {
+        let undo = self.counterpart(&redo);
+        self.restore(redo);
+        self.update_transaction_pool(block);
+        self.blocks.push_active(block.hash());
+        self.undo.push(undo);
+    }
+
+    // Current values of everything `record` covers, to move back across the
+    // same block in the other direction.
+    fn counterpart(&self, record: &BlockUndo) -> BlockUndo {
+        BlockUndo {
+            accounts: record
+                .accounts
+                .iter()
+                .map(|(account_id, _)| (account_id.clone(), self.accounts.get(account_id).cloned()))
+                .collect(),
+            locked_rewards: self.locked_rewards.clone(),
+            issued_supply: self.issued_supply,
+            target: self.target,
+        }
+    }
+
+    fn block_undo(&self, block: &Block) -> BlockUndo {
+        let mut touched: HashSet<&AccountId> =
+            self.locked_rewards.iter().map(|(_, account_id, _)| account_id).collect();
+        for tx in &block.transactions {
+            touched.extend(tx.from.as_ref());
+            match &tx.data {
+                TransactionData::CreateAccount(account_id, _)
+                | TransactionData::CreateMultisigAccount { account_id, .. } => touched.insert(account_id),
+                TransactionData::MintInitialSupply { to, .. }
+                | TransactionData::Transfer { to, .. }
+                | TransactionData::Coinbase { to, .. } => touched.insert(to),
+            };
+        }
+
+        BlockUndo {
+            accounts: touched
+                .into_iter()
+                .map(|account_id| (account_id.clone(), self.accounts.get(account_id).cloned()))
+                .collect(),
+            locked_rewards: self.locked_rewards.clone(),
+            issued_supply: self.issued_supply,
+            target: self.target,
+        }
+    }
+
+    fn restore(&mut self, undo: BlockUndo) {
+        for (account_id, account) in undo.accounts {
+            self.state.update(&account_id, account.as_ref());
+            match account {
+                Some(account) => self.accounts.insert(account_id, account),
+                None => self.accounts.remove(&account_id),
+            };
+        }
+        self.locked_rewards = undo.locked_rewards;
+        self.issued_supply = undo.issued_supply;
+        self.target = undo.target;
+    }
+
+    // The coinbase is optional. A block without one issues no subsidy, and the
+    // fees its transfers pay are debited from the senders but credited to no
+    // one, so they leave the supply for good.
+    fn check_coinbase(&self, block: &Block) -> Result<Balance, BlockError> {
+        if let Some(index) = block.transactions.iter().skip(1).position(|tx| tx.is_coinbase()) {
+            return Err(BlockError::MisplacedCoinbase { index: index + 1 });
+        }
+
+        let amount = match block.transactions.first().map(|tx| &tx.data) {
+            Some(TransactionData::Coinbase { amount, .. }) => *amount,
+            _ => return Ok(0),
+        };
+
+        let subsidy = self.next_block_subsidy();
+        let expected = subsidy
+            .checked_add(block.fees())
+            .ok_or(BlockError::CoinbaseOverflow)?;
+        if amount != expected {
+            return Err(BlockError::InvalidCoinbaseAmount {
+                expected,
+                got: amount,
+            });
+        }
+        Ok(subsidy)
+    }
+
+    fn check_supply(&self, block: &Block, subsidy: Balance) -> Result<Balance, BlockError> {
+        let mut issued_supply = self.issued_supply.checked_add(subsidy);
+        for tx in &block.transactions {
+            if let TransactionData::MintInitialSupply { amount, .. } = &tx.data {
+                issued_supply = issued_supply.and_then(|supply| supply.checked_add(*amount));
             }
         }
 
-        // TODO Task 3: Append block only if block.hash < target
-        // Adjust difficulty of target each block generation (epoch)
-        if !is_genesis {
-            let target = Bits::from_str_radix(&self.target.clone(), 16).unwrap();
-            if !(get_bits_from_hash(block.hash.as_ref().unwrap().clone()) < target) {
-                return Err("Hash greater than target".to_string());
+        match issued_supply {
+            Some(supply) if supply <= self.params.max_supply => Ok(supply),
+            _ => Err(BlockError::ExceedsMaxSupply),
+        }
+    }
+
+    fn lock_reward(&mut self, height: usize, account_id: AccountId, amount: Balance) {
+        if self.params.coinbase_maturity == 0 {
+            return;
+        }
+        if let Some(account) = self.accounts.get_mut(&account_id) {
+            account.locked += amount;
+            self.locked_rewards
+                .push_back((height + self.params.coinbase_maturity, account_id, amount));
+        }
+    }
+
+    fn release_rewards(&mut self, height: usize) {
+        while let Some((mature_height, _, _)) = self.locked_rewards.front() {
+            if *mature_height > height {
+                break;
+            }
+            let (_, account_id, amount) = self.locked_rewards.pop_front().unwrap();
+            if let Some(account) = self.accounts.get_mut(&account_id) {
+                account.locked -= amount;
             }
-            self.last_block_timestamp = get_timestamp();
-            self.update_target();
-            self.update_difficulty();
         }
+    }
+
+    fn update_transaction_pool(&mut self, block: &Block) {
+        let included: HashSet<Hash> = block.transactions.iter().map(|tx| tx.hash()).collect();
+        let accounts = &self.accounts;
+        self.transaction_pool.retain(|tx| {
+            if included.contains(&tx.hash()) {
+                return false;
+            }
+            match (&tx.data, &tx.from) {
+                (TransactionData::CreateAccount(account_id, _), _)
+                | (TransactionData::CreateMultisigAccount { account_id, .. }, _) => {
+                    !accounts.contains_key(account_id)
+                }
+                (_, Some(from)) => matches!(accounts.get(from), Some(sender) if tx.nonce >= sender.nonce),
+                _ => false,
+            }
+        });
+    }
 
-        self.first_block_timestamp = get_timestamp();
-        self.blocks.append(block);
+    fn check_nonce_order(block: &Block) -> Result<(), BlockError> {
+        let mut last_nonces: HashMap<&AccountId, u128> = HashMap::new();
+        for (index, tx) in block.transactions.iter().enumerate() {
+            if let Some(from) = &tx.from {
+                if let Some(last_nonce) = last_nonces.insert(from, tx.nonce) {
+                    if tx.nonce < last_nonce {
+                        return Err(BlockError::NonceOrder {
+                            index,
+                            account_id: from.clone(),
+                        });
+                    }
+                }
+            }
+        }
         Ok(())
     }
 
-    pub fn validate(&self) -> Result<(), Error> {
+    pub fn validate(&self) -> Result<(), ValidationError> {
         let mut block_num = self.blocks.len();
         let mut prev_block_hash: Option<Hash> = None;
 
@@ -100,61 +628,187 @@ impl Blockchain {
             let is_genesis = block_num == 1;
 
             if !block.verify() {
-                return Err(format!("Block {} has invalid hash", block_num));
+                return Err(ValidationError::InvalidHash {
+                    height: block_num - 1,
+                });
             }
 
-            if !is_genesis && block.prev_hash.is_none() {
-                return Err(format!("Block {} doesn't have prev_hash", block_num));
+            if !is_genesis && block.prev_hash().is_none() {
+                return Err(ValidationError::MissingPrevHash {
+                    height: block_num - 1,
+                });
             }
 
-            if is_genesis && block.prev_hash.is_some() {
-                return Err("Genesis block shouldn't have prev_hash".to_string());
+            if is_genesis && block.prev_hash().is_some() {
+                return Err(ValidationError::GenesisPrevHash);
             }
 
             if block_num != self.blocks.len() {
                 if let Some(prev_block_hash) = &prev_block_hash {
                     if prev_block_hash != &block.hash.clone().unwrap() {
-                        return Err(format!(
-                            "Block {} prev_hash doesn't match Block {} hash",
-                            block_num + 1,
-                            block_num
-                        ));
+                        return Err(ValidationError::BrokenLink { height: block_num });
                     }
                 }
             }
 
-            prev_block_hash = block.prev_hash.clone();
+            prev_block_hash = block.prev_hash().cloned();
             block_num -= 1;
         }
 
         Ok(())
     }
 
+    pub fn validate_full(&self) -> Result<(), ValidationError> {
+        Self::replay(&self.params, self.blocks.iter().rev(), &self.accounts)
+    }
+
+    // Copies what `validate_full` needs, so a shared chain only has to be
+    // locked for the copy and not for the whole replay.
+    pub fn snapshot(&self) -> ChainSnapshot {
+        ChainSnapshot {
+            params: self.params.clone(),
+            blocks: self.blocks.iter().rev().cloned().collect(),
+            accounts: self.accounts.clone(),
+        }
+    }
+
+    fn replay<'a>(
+        params: &ChainParams,
+        blocks: impl Iterator<Item = &'a Block>,
+        accounts: &HashMap<AccountId, Account>,
+    ) -> Result<(), ValidationError> {
+        let mut replay = Self::with_params(params.clone());
+        for (height, block) in blocks.enumerate() {
+            replay
+                .append_block(block.clone())
+                .map_err(|error| ValidationError::InvalidBlock { height, error })?;
+        }
+
+        let mut account_ids: Vec<&AccountId> =
+            accounts.keys().chain(replay.accounts.keys()).collect();
+        account_ids.sort();
+        account_ids.dedup();
+        for account_id in account_ids {
+            if accounts.get(account_id) != replay.accounts.get(account_id) {
+                return Err(ValidationError::AccountMismatch {
+                    account_id: account_id.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn get_last_block_hash(&self) -> Option<Hash> {
-        self.blocks.head().map(|block| block.hash())
+        self.blocks.tip_hash().cloned()
+    }
+
+    // Median timestamp of the last MEDIAN_TIME_SPAN blocks. A new block's
+    // timestamp must be later than this.
+    pub fn median_time_past(&self) -> Timestamp {
+        match self.blocks.tip_hash() {
+            Some(tip) => self.median_time_past_after(tip),
+            None => 0,
+        }
+    }
+
+    fn median_time_past_after(&self, hash: &Hash) -> Timestamp {
+        let headers: Vec<&BlockHeader> = self
+            .blocks
+            .ancestors(hash, MEDIAN_TIME_SPAN)
+            .into_iter()
+            .map(|block| block.header())
+            .collect();
+        Self::median_time(&headers)
+    }
+
+    fn median_time(headers: &[&BlockHeader]) -> Timestamp {
+        let mut timestamps: Vec<Timestamp> = headers[headers.len().saturating_sub(MEDIAN_TIME_SPAN)..]
+            .iter()
+            .map(|header| header.timestamp)
+            .collect();
+        timestamps.sort_unstable();
+        timestamps.get(timestamps.len() / 2).copied().unwrap_or(0)
+    }
+
+    // Checks a run of linked headers against the bits and median time past of
+    // their own branch, so a peer's headers can be vetted before their blocks
+    // are fetched. The first header must extend a known block or be genesis.
+    pub fn check_headers(&self, headers: &[BlockHeader]) -> Result<(), BlockError> {
+        let first = match headers.first() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+        let algorithm = &self.params.difficulty_algorithm;
+        let span = algorithm.window().max(MEDIAN_TIME_SPAN);
+        let (mut context, height): (Vec<&BlockHeader>, usize) = match first.prev_hash() {
+            Some(prev_hash) => {
+                let height = self.blocks.height(prev_hash).ok_or(BlockError::UnknownParent)?;
+                let ancestors = self.blocks.ancestors(prev_hash, span);
+                (ancestors.into_iter().map(|block| block.header()).collect(), height + 1)
+            }
+            None => (vec![], 0),
+        };
+        let genesis = match self.blocks.get_by_height(0) {
+            Some(genesis) => genesis.header(),
+            None => first,
+        };
+
+        for (index, header) in headers.iter().enumerate() {
+            if height + index > 0 {
+                let start = context.len().saturating_sub(algorithm.window());
+                let target = self.target_for(height + index, genesis, &context[start..]);
+                Self::check_header(header, target, Self::median_time(&context))?;
+            }
+            context.push(header);
+        }
+        Ok(())
+    }
+
+    pub fn target(&self) -> Target {
+        self.target
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        let max_target = self.params.difficulty_algorithm.max_target();
+        (max_target.as_f64() / self.target.as_f64()) as Difficulty
+    }
+
+    fn next_target(&self) -> Target {
+        match self.blocks.tip_hash() {
+            Some(tip) => self.target_after(tip),
+            None => self.params.difficulty_algorithm.max_target(),
+        }
     }
 
-    pub fn update_difficulty(&mut self) {
-        let current_target = Bits::from_str_radix(&self.target.clone(), 16).unwrap();
-        self.difficulty = MAX_TARGET as f32 / current_target as f32;
-        println!("new difficulty {}", self.difficulty.clone());
+    // Target of a block built on `hash`, which need not be on the active chain.
+    fn target_after(&self, hash: &Hash) -> Target {
+        let algorithm = &self.params.difficulty_algorithm;
+        let (genesis, height) = match (self.blocks.get_by_height(0), self.blocks.height(hash)) {
+            (Some(genesis), Some(height)) => (genesis.header(), height + 1),
+            _ => return algorithm.max_target(),
+        };
+
+        let headers: Vec<&BlockHeader> = self
+            .blocks
+            .ancestors(hash, algorithm.window())
+            .into_iter()
+            .map(|block| block.header())
+            .collect();
+        self.target_for(height, genesis, &headers)
     }
 
-    pub fn update_target(&mut self) {
-        let actual_time = (self.last_block_timestamp.clone() - self.first_block_timestamp.clone()) as i32;
-        let mut ratio = actual_time as f32 / (self.len() as i32 * EXPECTED_TIME) as f32;
-        ratio = if ratio > 4. { 4. } else if ratio < 0.25 { 0.25 } else { ratio };
-        let current_target = Bits::from_str_radix(&self.target.clone(), 16).unwrap();
-        let mut new_target = (current_target as f32 * ratio) as i32;
-        new_target = if new_target > MAX_TARGET { MAX_TARGET } else { new_target };
-        self.target = format!("{:x}", new_target);
-        println!("new target {} with ratio {}", self.target.clone(), ratio.clone());
+    fn target_for(&self, height: usize, genesis: &BlockHeader, headers: &[&BlockHeader]) -> Target {
+        let target = self.params.difficulty_algorithm.next_target(height, genesis, headers);
+        U256::from_compact(target.to_compact()).unwrap_or(target)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::{append_block, append_block_with_tx, create_account_tx, create_transfer_tx, mint_initial_supply};
+    use crate::difficulty::Asert;
+    use crate::types::{AccountType, MAX_FUTURE_BLOCK_TIME};
+    use crate::utils::{append_block, append_block_with_tx, create_account_tx, create_coinbase_tx, create_multisig_account_tx, create_transfer_tx, generate_account_id, mint_initial_supply, new_block};
 
     use super::*;
 
@@ -167,8 +821,8 @@ mod tests {
     #[test]
     fn test_append() {
         let bc = &mut Blockchain::new();
-        append_block(bc, 1);
-        let block = append_block(bc, 2);
+        append_block(bc);
+        let block = append_block(bc);
 
         assert_eq!(bc.get_last_block_hash(), block.hash);
     }
@@ -182,7 +836,7 @@ mod tests {
         let tx_mint_initial_supply = mint_initial_supply(account.clone(), 100_000_000);
 
         assert!(
-            append_block_with_tx(bc, 1, vec![tx_create_account, tx_mint_initial_supply]).is_ok()
+            append_block_with_tx(bc, vec![tx_create_account, tx_mint_initial_supply]).is_ok()
         );
 
         let satoshi = bc.get_account_by_id(account.clone());
@@ -207,7 +861,12 @@ mod tests {
 
         assert_eq!(
             bc.append_block(block).err().unwrap(),
-            "Error during tx execution: Invalid account.".to_string()
+            BlockError::Transaction {
+                index: 0,
+                error: TxError::InvalidAccount {
+                    account_id: account.clone(),
+                },
+            }
         );
     }
 
@@ -223,10 +882,11 @@ mod tests {
         block.set_nonce(1);
         block.add_transaction(tx_create_account);
         block.add_transaction(tx_mint_initial_supply);
+        block.set_state_root(bc.state_root_after(&block).unwrap());
 
         assert!(bc.append_block(block).is_ok());
 
-        let mut block = Block::new(bc.get_last_block_hash());
+        let mut block = new_block(&bc);
 
         let account_alice = "alice".to_string();
         let (_, tx_create_alice) = create_account_tx(account_alice.clone());
@@ -256,18 +916,15 @@ mod tests {
         let tx_mint_initial_supply = mint_initial_supply(account.clone(), 100_000_000);
 
         assert!(
-            append_block_with_tx(bc, 1, vec![tx_create_account, tx_mint_initial_supply]).is_ok()
+            append_block_with_tx(bc, vec![tx_create_account, tx_mint_initial_supply]).is_ok()
         );
 
-        append_block(bc, 2);
-        append_block(bc, 3);
+        append_block(bc);
+        append_block(bc);
 
         assert!(bc.validate().is_ok());
 
-        let mut iter = bc.blocks.iter_mut();
-        iter.next();
-        iter.next();
-        let block = iter.next().unwrap();
+        let block = bc.blocks.get_by_height_mut(0).unwrap();
         block.transactions[1].data = mint_initial_supply(account.clone(), 100).data;
 
         assert!(bc.validate().is_err());
@@ -278,21 +935,21 @@ mod tests {
         let bc = &mut Blockchain::new();
 
         let account_id_satoshi = "satoshi".to_string();
-        let (_, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+        let (keypair_satoshi, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
         let tx_mint_initial_supply = mint_initial_supply(account_id_satoshi.clone(), 100_000_000);
 
         assert!(
-            append_block_with_tx(bc, 1, vec![tx_create_satoshi, tx_mint_initial_supply]).is_ok()
+            append_block_with_tx(bc, vec![tx_create_satoshi, tx_mint_initial_supply]).is_ok()
         );
 
         let account_id_alice = "alice".to_string();
-        let (keypair_alice, tx_create_alice) = create_account_tx(account_id_alice.clone());
+        let (_, tx_create_alice) = create_account_tx(account_id_alice.clone());
 
         let account_id_bob = "bob".to_string();
         let (keypair_bob, tx_create_bob) = create_account_tx(account_id_bob.clone());
 
         assert!(
-            append_block_with_tx(bc, 2, vec![tx_create_alice, tx_create_bob]).is_ok()
+            append_block_with_tx(bc, vec![tx_create_alice, tx_create_bob]).is_ok()
         );
 
         assert!(bc.get_account_by_id("satoshi".to_string()).is_some());
@@ -303,45 +960,57 @@ mod tests {
             account_id_satoshi.clone(),
             account_id_alice.clone(),
             10_000_000,
+            0,
+            0,
         );
-        tx_tr_from_satoshi_alice.sign(&keypair_alice);
+        tx_tr_from_satoshi_alice.sign(&keypair_satoshi);
 
         let mut tx_tr_from_satoshi_to_bob = create_transfer_tx(
             account_id_satoshi.clone(),
             account_id_bob.clone(),
             50_000_000,
+            0,
+            1,
         );
-        tx_tr_from_satoshi_to_bob.sign(&keypair_bob);
+        tx_tr_from_satoshi_to_bob.sign(&keypair_satoshi);
 
         let mut tx_tr_from_bob_to_sastoshi = create_transfer_tx(
             account_id_bob.clone(),
             account_id_satoshi.clone(),
             30_000_000,
+            0,
+            0,
         );
         tx_tr_from_bob_to_sastoshi.sign(&keypair_bob);
 
         assert!(
-            append_block_with_tx(bc, 3, vec![
-                tx_tr_from_satoshi_alice,
+            append_block_with_tx(bc, vec![
+                tx_tr_from_satoshi_alice.clone(),
                 tx_tr_from_satoshi_to_bob,
                 tx_tr_from_bob_to_sastoshi,
             ]).is_ok()
         );
 
+        assert_eq!(
+            append_block_with_tx(bc, vec![tx_tr_from_satoshi_alice]).err().unwrap(),
+            BlockError::Transaction {
+                index: 0,
+                error: TxError::NonceUsed {
+                    expected: 2,
+                    got: 0,
+                },
+            }
+        );
+        assert_eq!(bc.get_account_by_id("satoshi".to_string()).unwrap().nonce, 2);
+
         let alice_new_account = bc.get_account_by_id("alice".to_string());
-        if alice_new_account.is_some() {
-            assert_eq!(alice_new_account.unwrap().balance, 10_000_000)
-        }
+        assert_eq!(alice_new_account.unwrap().balance, 10_000_000);
 
         let bob_new_account = bc.get_account_by_id("bob".to_string());
-        if bob_new_account.is_some() {
-            assert_eq!(bob_new_account.unwrap().balance, 20_000_000)
-        }
+        assert_eq!(bob_new_account.unwrap().balance, 20_000_000);
 
         let satpshi_new_account = bc.get_account_by_id("satoshi".to_string());
-        if satpshi_new_account.is_some() {
-            assert_eq!(satpshi_new_account.unwrap().balance, 70_000_000)
-        }
+        assert_eq!(satpshi_new_account.unwrap().balance, 70_000_000);
     }
 
     #[test]
@@ -353,7 +1022,7 @@ mod tests {
         let tx_mint_initial_supply = mint_initial_supply(account_id_satoshi.clone(), 100_000_000);
 
         assert!(
-            append_block_with_tx(bc, 1, vec![
+            append_block_with_tx(bc, vec![
                 tx_create_satoshi,
                 tx_mint_initial_supply,
             ]).is_ok()
@@ -367,13 +1036,13 @@ mod tests {
         let (_, tx_create_bob) = create_account_tx(account_id_bob.clone());
 
         assert!(
-            append_block_with_tx(bc, 2, vec![
+            append_block_with_tx(bc, vec![
                 tx_create_alice,
                 tx_create_bob,
             ]).is_ok()
         );
 
-        for acc_id in vec![account_id_satoshi.clone(), account_id_bob.clone(), account_id_alice.clone()] {
+        for acc_id in [account_id_satoshi.clone(), account_id_bob.clone(), account_id_alice.clone()] {
             assert!(bc.get_account_by_id(acc_id.to_string()).is_some());
         }
 
@@ -381,47 +1050,75 @@ mod tests {
             account_id_satoshi.clone(),
             account_id_satoshi.clone(),
             10_000_000,
+            0,
+            0,
         );
         tx_tr_self.sign(&keypair_satoshi);
 
         assert_eq!(
-            append_block_with_tx(bc, 2, vec![tx_tr_self]).err().unwrap(),
-            "Error during tx execution: Transfer to yourself.".to_string()
+            append_block_with_tx(bc, vec![tx_tr_self]).err().unwrap(),
+            BlockError::Transaction {
+                index: 0,
+                error: TxError::SelfTransfer,
+            }
         );
 
         let mut tx_tr_gt_balance = create_transfer_tx(
             account_id_satoshi.clone(),
             account_id_bob.clone(),
             100_000_000_000,
+            0,
+            0,
         );
         tx_tr_gt_balance.sign(&keypair_satoshi);
 
         assert_eq!(
-            append_block_with_tx(bc, 3, vec![tx_tr_gt_balance]).err().unwrap(),
-            "Error during tx execution: Sender doesn't have enough currency.".to_string()
+            append_block_with_tx(bc, vec![tx_tr_gt_balance]).err().unwrap(),
+            BlockError::Transaction {
+                index: 0,
+                error: TxError::InsufficientBalance {
+                    account_id: account_id_satoshi.clone(),
+                    required: 100_000_000_000,
+                    available: 100_000_000,
+                },
+            }
         );
 
         let mut tx_tr_from_satoshi_to_invalid = create_transfer_tx(
             account_id_satoshi.clone(),
             "invalid".to_string(),
             1,
+            0,
+            0,
         );
         tx_tr_from_satoshi_to_invalid.sign(&keypair_satoshi);
 
         assert_eq!(
-            append_block_with_tx(bc, 4, vec![tx_tr_from_satoshi_to_invalid]).err().unwrap(),
-            "Error during tx execution: Invalid receiver account.".to_string()
+            append_block_with_tx(bc, vec![tx_tr_from_satoshi_to_invalid]).err().unwrap(),
+            BlockError::Transaction {
+                index: 0,
+                error: TxError::InvalidReceiver {
+                    account_id: "invalid".to_string(),
+                },
+            }
         );
 
         let tx_tr_from_invalid_to_satoshi = create_transfer_tx(
             "invalid".to_string(),
             account_id_satoshi.clone(),
             1,
+            0,
+            0,
         );
 
         assert_eq!(
-            append_block_with_tx(bc, 4, vec![tx_tr_from_invalid_to_satoshi]).err().unwrap(),
-            "Error during tx execution: Invalid sender account.".to_string()
+            append_block_with_tx(bc, vec![tx_tr_from_invalid_to_satoshi]).err().unwrap(),
+            BlockError::Transaction {
+                index: 0,
+                error: TxError::InvalidSender {
+                    account_id: "invalid".to_string(),
+                },
+            }
         );
     }
 
@@ -434,7 +1131,7 @@ mod tests {
         let tx_mint_initial_supply = mint_initial_supply(account_id_satoshi.clone(), 100_000_000);
 
         assert!(
-            append_block_with_tx(bc, 1, vec![
+            append_block_with_tx(bc, vec![
                 tx_create_satoshi,
                 tx_mint_initial_supply,
             ]).is_ok()
@@ -448,7 +1145,7 @@ mod tests {
         let (keypair_bob, tx_create_bob) = create_account_tx(account_id_bob.clone());
 
         assert!(
-            append_block_with_tx(bc, 2, vec![
+            append_block_with_tx(bc, vec![
                 tx_create_alice,
                 tx_create_bob,
             ]).is_ok()
@@ -458,12 +1155,14 @@ mod tests {
             account_id_satoshi.clone(),
             account_id_bob.clone(),
             1,
+            0,
+            0,
         );
 
         tx_tr_from_satoshi_to_bob_wtih_fake_sign.sign(&keypair_bob);
 
         assert!(
-            append_block_with_tx(bc, 2, vec![
+            append_block_with_tx(bc, vec![
                 tx_tr_from_satoshi_to_bob_wtih_fake_sign
             ]).is_err()
         );
@@ -472,6 +1171,8 @@ mod tests {
             account_id_satoshi.clone(),
             account_id_bob.clone(),
             1,
+            0,
+            0,
         );
         tx_tr_from_satoshi_to_bob_wtih_fake_data.sign(&keypair_satoshi);
 
@@ -479,12 +1180,993 @@ mod tests {
             account_id_satoshi.clone(),
             account_id_bob.clone(),
             500,
+            0,
+            0,
         );
         tx_tr_from_satoshi_to_bob_wtih_fake_data.data = tx_fake.data;
         assert!(
-            append_block_with_tx(bc, 2, vec![
+            append_block_with_tx(bc, vec![
                 tx_tr_from_satoshi_to_bob_wtih_fake_data
             ]).is_err()
         );
     }
+    #[test]
+    fn test_multisig() {
+        let bc = &mut Blockchain::new();
+
+        let account_id_treasury = "treasury".to_string();
+        let (keypairs, tx_create_treasury) = create_multisig_account_tx(account_id_treasury.clone(), 2, 3);
+        let account_id_alice = "alice".to_string();
+        let (keypair_alice, tx_create_alice) = create_account_tx(account_id_alice.clone());
+        assert!(
+            append_block_with_tx(bc, vec![
+                tx_create_treasury,
+                mint_initial_supply(account_id_treasury.clone(), 1_000),
+                tx_create_alice,
+            ]).is_ok()
+        );
+
+        let treasury = bc.get_account_by_id(account_id_treasury.clone()).unwrap();
+        assert_eq!(treasury.account_type(), &AccountType::Multisig);
+        assert_eq!(treasury.public_keys().len(), 3);
+        assert_eq!(treasury.threshold(), 2);
+
+        let transfer = create_transfer_tx(account_id_treasury.clone(), account_id_alice.clone(), 100, 0, 0);
+        let invalid_signature = BlockError::Transaction {
+            index: 0,
+            error: TxError::InvalidSignature,
+        };
+
+        let mut tx = transfer.clone();
+        tx.cosign(&keypairs[0]);
+        assert_eq!(append_block_with_tx(bc, vec![tx.clone()]).err().unwrap(), invalid_signature);
+
+        let mut duplicate = tx.clone();
+        duplicate.cosign(&keypairs[0]);
+        assert_eq!(append_block_with_tx(bc, vec![duplicate]).err().unwrap(), invalid_signature);
+
+        let mut foreign = tx.clone();
+        foreign.cosign(&keypair_alice);
+        assert_eq!(append_block_with_tx(bc, vec![foreign]).err().unwrap(), invalid_signature);
+
+        tx.cosign(&keypairs[2]);
+        assert!(bc.submit_transaction(tx.clone()).is_ok());
+        assert!(append_block_with_tx(bc, vec![tx]).is_ok());
+        assert_eq!(bc.get_account_by_id(account_id_alice.clone()).unwrap().balance(), 100);
+
+        let mut tx = create_transfer_tx(account_id_treasury.clone(), account_id_alice, 100, 0, 1);
+        for keypair in &keypairs {
+            tx.cosign(keypair);
+        }
+        assert!(append_block_with_tx(bc, vec![tx]).is_ok());
+        assert_eq!(bc.get_account_by_id(account_id_treasury).unwrap().balance(), 800);
+
+        let (_, tx_create_invalid) = create_multisig_account_tx("invalid".to_string(), 3, 2);
+        assert_eq!(
+            append_block_with_tx(bc, vec![tx_create_invalid]).err().unwrap(),
+            BlockError::Transaction {
+                index: 0,
+                error: TxError::InvalidThreshold { threshold: 3, keys: 2 },
+            }
+        );
+
+        let (keypairs, _) = create_multisig_account_tx("invalid".to_string(), 1, 1);
+        let public_key = *keypairs[0].public.as_bytes();
+        let tx_create_duplicate = Transaction::new(
+            TransactionData::CreateMultisigAccount {
+                account_id: "invalid".to_string(),
+                public_keys: vec![public_key, public_key],
+                threshold: 1,
+            },
+            None,
+        );
+        assert_eq!(
+            append_block_with_tx(bc, vec![tx_create_duplicate]).err().unwrap(),
+            BlockError::Transaction {
+                index: 0,
+                error: TxError::DuplicatePublicKey,
+            }
+        );
+    }
+
+    #[test]
+    fn test_transfers_nonce() {
+        let bc = &mut Blockchain::new();
+
+        let account_id_satoshi = "satoshi".to_string();
+        let (keypair_satoshi, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+        let tx_mint_initial_supply = mint_initial_supply(account_id_satoshi.clone(), 100_000_000);
+
+        let account_id_alice = "alice".to_string();
+        let (_, tx_create_alice) = create_account_tx(account_id_alice.clone());
+
+        assert!(
+            append_block_with_tx(bc, vec![
+                tx_create_satoshi,
+                tx_mint_initial_supply,
+                tx_create_alice,
+            ]).is_ok()
+        );
+
+        let mut tx_tr_nonce_0 = create_transfer_tx(
+            account_id_satoshi.clone(),
+            account_id_alice.clone(),
+            1,
+            0,
+            0,
+        );
+        tx_tr_nonce_0.sign(&keypair_satoshi);
+
+        let mut tx_tr_nonce_1 = create_transfer_tx(
+            account_id_satoshi.clone(),
+            account_id_alice.clone(),
+            1,
+            0,
+            1,
+        );
+        tx_tr_nonce_1.sign(&keypair_satoshi);
+
+        assert_eq!(
+            append_block_with_tx(bc, vec![tx_tr_nonce_1.clone()]).err().unwrap(),
+            BlockError::Transaction {
+                index: 0,
+                error: TxError::NonceGap {
+                    expected: 0,
+                    got: 1,
+                },
+            }
+        );
+
+        assert_eq!(
+            append_block_with_tx(bc, vec![
+                tx_tr_nonce_1.clone(),
+                tx_tr_nonce_0.clone(),
+            ]).err().unwrap(),
+            BlockError::NonceOrder {
+                index: 1,
+                account_id: account_id_satoshi.clone(),
+            }
+        );
+
+        assert_eq!(
+            append_block_with_tx(bc, vec![
+                tx_tr_nonce_0.clone(),
+                tx_tr_nonce_0.clone(),
+            ]).err().unwrap(),
+            BlockError::Transaction {
+                index: 1,
+                error: TxError::NonceUsed {
+                    expected: 1,
+                    got: 0,
+                },
+            }
+        );
+
+        assert!(append_block_with_tx(bc, vec![tx_tr_nonce_0, tx_tr_nonce_1]).is_ok());
+        assert_eq!(bc.get_account_by_id(account_id_alice).unwrap().balance, 2);
+    }
+    #[test]
+    fn test_coinbase() {
+        let bc = &mut Blockchain::new();
+
+        let account_id_satoshi = "satoshi".to_string();
+        let (keypair_satoshi, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+        let tx_mint_initial_supply = mint_initial_supply(account_id_satoshi.clone(), 100_000_000);
+
+        let account_id_alice = "alice".to_string();
+        let (_, tx_create_alice) = create_account_tx(account_id_alice.clone());
+
+        let account_id_bob = "bob".to_string();
+        let (_, tx_create_bob) = create_account_tx(account_id_bob.clone());
+
+        assert!(
+            append_block_with_tx(bc, vec![
+                tx_create_satoshi,
+                tx_mint_initial_supply,
+                tx_create_alice,
+                tx_create_bob,
+            ]).is_ok()
+        );
+
+        let mut tx_tr_from_satoshi_to_alice = create_transfer_tx(
+            account_id_satoshi.clone(),
+            account_id_alice.clone(),
+            10_000_000,
+            1_000,
+            0,
+        );
+        tx_tr_from_satoshi_to_alice.sign(&keypair_satoshi);
+
+        let reward = bc.next_block_subsidy();
+        let mut block = new_block(bc);
+        block.add_transaction(tx_tr_from_satoshi_to_alice.clone());
+        block.add_coinbase(account_id_bob.clone(), reward + 1);
+        block.mine(bc.target).unwrap();
+        assert_eq!(
+            bc.append_block(block).err().unwrap(),
+            BlockError::InvalidCoinbaseAmount {
+                expected: reward + 1_000,
+                got: reward + 1_001,
+            }
+        );
+
+        let mut block = new_block(bc);
+        block.add_transaction(tx_tr_from_satoshi_to_alice.clone());
+        block.add_transaction(create_coinbase_tx(account_id_bob.clone(), reward));
+        block.mine(bc.target).unwrap();
+        assert_eq!(
+            bc.append_block(block).err().unwrap(),
+            BlockError::MisplacedCoinbase { index: 1 }
+        );
+
+        let mut block = new_block(bc);
+        block.add_transaction(tx_tr_from_satoshi_to_alice);
+        block.add_coinbase(account_id_bob.clone(), reward);
+        block.set_state_root(bc.state_root_after(&block).unwrap());
+        block.mine(bc.target).unwrap();
+        assert!(bc.append_block(block).is_ok());
+
+        let satoshi = bc.get_account_by_id(account_id_satoshi.clone()).unwrap();
+        assert_eq!(satoshi.balance, 100_000_000 - 10_000_000 - 1_000);
+
+        let alice = bc.get_account_by_id(account_id_alice.clone()).unwrap();
+        assert_eq!(alice.balance, 10_000_000);
+
+        let bob = bc.get_account_by_id(account_id_bob.clone()).unwrap();
+        assert_eq!(bob.balance, reward + 1_000);
+
+        // Leaving out the coinbase forfeits the subsidy and burns the fees.
+        let issued_supply = bc.issued_supply();
+        let mut tx = create_transfer_tx(account_id_satoshi.clone(), account_id_alice.clone(), 1, 500, 1);
+        tx.sign(&keypair_satoshi);
+        assert!(append_block_with_tx(bc, vec![tx]).is_ok());
+        assert_eq!(bc.issued_supply(), issued_supply);
+        assert_eq!(
+            bc.get_account_by_id(account_id_satoshi).unwrap().balance,
+            100_000_000 - 10_000_000 - 1_000 - 1 - 500
+        );
+        assert_eq!(bc.get_account_by_id(account_id_alice).unwrap().balance, 10_000_001);
+        assert_eq!(bc.get_account_by_id(account_id_bob).unwrap().balance, reward + 1_000);
+    }
+    #[test]
+    fn test_coinbase_maturity() {
+        let bc = &mut Blockchain::with_params(ChainParams {
+            coinbase_maturity: 2,
+            ..Default::default()
+        });
+
+        let account_id_satoshi = "satoshi".to_string();
+        let (_, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+
+        let account_id_alice = "alice".to_string();
+        let (keypair_alice, tx_create_alice) = create_account_tx(account_id_alice.clone());
+
+        assert!(append_block_with_tx(bc, vec![tx_create_satoshi, tx_create_alice]).is_ok());
+
+        let reward = bc.next_block_subsidy();
+        let mut block = new_block(bc);
+        block.add_coinbase(account_id_alice.clone(), reward);
+        block.set_state_root(bc.state_root_after(&block).unwrap());
+        block.mine(bc.target).unwrap();
+        assert!(bc.append_block(block).is_ok());
+
+        let alice = bc.get_account_by_id(account_id_alice.clone()).unwrap();
+        assert_eq!(alice.balance(), reward);
+        assert_eq!(alice.locked_balance(), reward);
+
+        let mut tx_tr_from_alice_to_satoshi = create_transfer_tx(
+            account_id_alice.clone(),
+            account_id_satoshi.clone(),
+            reward,
+            0,
+            0,
+        );
+        tx_tr_from_alice_to_satoshi.sign(&keypair_alice);
+
+        assert_eq!(
+            append_block_with_tx(bc, vec![tx_tr_from_alice_to_satoshi.clone()]).err().unwrap(),
+            BlockError::Transaction {
+                index: 0,
+                error: TxError::ImmatureBalance {
+                    account_id: account_id_alice.clone(),
+                    required: reward,
+                    available: 0,
+                },
+            }
+        );
+
+        append_block(bc);
+        assert_eq!(bc.get_account_by_id(account_id_alice.clone()).unwrap().locked_balance(), 0);
+
+        assert!(append_block_with_tx(bc, vec![tx_tr_from_alice_to_satoshi]).is_ok());
+        assert_eq!(bc.get_account_by_id(account_id_satoshi).unwrap().balance(), reward);
+    }
+
+    #[test]
+    fn test_supply_cap() {
+        let bc = &mut Blockchain::with_params(ChainParams {
+            initial_subsidy: 100,
+            halving_interval: 2,
+            max_supply: 1_130,
+            coinbase_maturity: 0,
+            ..Default::default()
+        });
+
+        let account_id_satoshi = "satoshi".to_string();
+        let (_, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+        let tx_mint_too_much = mint_initial_supply(account_id_satoshi.clone(), 1_131);
+
+        assert_eq!(
+            append_block_with_tx(bc, vec![tx_create_satoshi.clone(), tx_mint_too_much]).err().unwrap(),
+            BlockError::ExceedsMaxSupply
+        );
+
+        let tx_mint_initial_supply = mint_initial_supply(account_id_satoshi.clone(), 1_000);
+        assert!(append_block_with_tx(bc, vec![tx_create_satoshi, tx_mint_initial_supply]).is_ok());
+        assert_eq!(bc.issued_supply(), 1_000);
+
+        for subsidy in [100, 30, 0] {
+            assert_eq!(bc.next_block_subsidy(), subsidy);
+
+            let mut block = new_block(bc);
+            block.add_coinbase(account_id_satoshi.clone(), subsidy);
+            block.set_state_root(bc.state_root_after(&block).unwrap());
+            block.mine(bc.target).unwrap();
+            assert!(bc.append_block(block).is_ok());
+        }
+
+        assert_eq!(bc.issued_supply(), 1_130);
+        assert_eq!(bc.get_account_by_id(account_id_satoshi).unwrap().balance(), 1_130);
+    }
+    #[test]
+    fn test_transaction_pool() {
+        let bc = &mut Blockchain::new();
+
+        let account_id_satoshi = "satoshi".to_string();
+        let (keypair_satoshi, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+        let tx_mint_initial_supply = mint_initial_supply(account_id_satoshi.clone(), 1_000);
+
+        let account_id_alice = "alice".to_string();
+        let (keypair_alice, tx_create_alice) = create_account_tx(account_id_alice.clone());
+
+        assert!(
+            append_block_with_tx(bc, vec![
+                tx_create_satoshi,
+                tx_mint_initial_supply,
+                tx_create_alice,
+            ]).is_ok()
+        );
+
+        let mut tx_tr_low_fee = create_transfer_tx(
+            account_id_satoshi.clone(),
+            account_id_alice.clone(),
+            100,
+            10,
+            0,
+        );
+        tx_tr_low_fee.sign(&keypair_satoshi);
+
+        let mut tx_tr_high_fee = create_transfer_tx(
+            account_id_satoshi.clone(),
+            account_id_alice.clone(),
+            100,
+            30,
+            1,
+        );
+        tx_tr_high_fee.sign(&keypair_satoshi);
+
+        assert!(bc.submit_transaction(tx_tr_low_fee.clone()).is_ok());
+        assert!(bc.submit_transaction(tx_tr_high_fee.clone()).is_ok());
+        assert_eq!(
+            bc.submit_transaction(tx_tr_low_fee.clone()).err().unwrap(),
+            PoolError::AlreadyInPool
+        );
+
+        let fees: Vec<_> = bc.transaction_pool().iter().map(|tx| tx.fee()).collect();
+        assert_eq!(fees, vec![30, 10]);
+
+        let mut tx_tr_fake_sign = create_transfer_tx(
+            account_id_satoshi.clone(),
+            account_id_alice.clone(),
+            100,
+            10,
+            2,
+        );
+        tx_tr_fake_sign.sign(&keypair_alice);
+        assert_eq!(
+            bc.submit_transaction(tx_tr_fake_sign).err().unwrap(),
+            PoolError::Transaction(TxError::InvalidSignature)
+        );
+
+        let mut tx_tr_gt_balance = create_transfer_tx(
+            account_id_satoshi.clone(),
+            account_id_alice.clone(),
+            800,
+            10,
+            2,
+        );
+        tx_tr_gt_balance.sign(&keypair_satoshi);
+        assert_eq!(
+            bc.submit_transaction(tx_tr_gt_balance).err().unwrap(),
+            PoolError::Transaction(TxError::InsufficientBalance {
+                account_id: account_id_satoshi.clone(),
+                required: 1_050,
+                available: 1_000,
+            })
+        );
+
+        assert_eq!(
+            bc.submit_transaction(create_coinbase_tx(account_id_alice.clone(), 1)).err().unwrap(),
+            PoolError::NotPoolable
+        );
+
+        assert!(append_block_with_tx(bc, vec![tx_tr_low_fee.clone()]).is_ok());
+        assert_eq!(bc.transaction_pool().len(), 1);
+        assert!(bc.transaction_pool().contains(&tx_tr_high_fee.hash()));
+
+        assert_eq!(
+            bc.submit_transaction(tx_tr_low_fee).err().unwrap(),
+            PoolError::Transaction(TxError::NonceUsed { expected: 1, got: 0 })
+        );
+    }
+    #[test]
+    fn test_state_root() {
+        let bc = &mut Blockchain::new();
+        assert_eq!(bc.state_root(), "00".repeat(32));
+
+        let account_id_satoshi = "satoshi".to_string();
+        let (_, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+        assert!(append_block_with_tx(bc, vec![tx_create_satoshi]).is_ok());
+        let state_root = bc.state_root();
+        assert_eq!(bc.blocks().tip().unwrap().header().state_root(), &state_root);
+
+        let mut block = new_block(bc);
+        block.add_transaction(create_account_tx("alice".to_string()).1);
+        let expected = bc.state_root_after(&block).unwrap();
+        assert_ne!(expected, state_root);
+        assert_eq!(bc.state_root(), state_root);
+
+        block.set_state_root(state_root.clone());
+        block.mine(bc.target).unwrap();
+        assert_eq!(
+            bc.append_block(block.clone()).err().unwrap(),
+            BlockError::StateRootMismatch {
+                expected: expected.clone(),
+                got: state_root.clone(),
+            }
+        );
+        assert!(bc.get_account_by_id("alice".to_string()).is_none());
+        assert_eq!(bc.state_root(), state_root);
+
+        block.set_state_root(expected.clone());
+        block.mine(bc.target).unwrap();
+        assert!(bc.append_block(block).is_ok());
+        assert_eq!(bc.state_root(), expected);
+        assert!(bc.validate_full().is_ok());
+    }
+
+    #[test]
+    fn test_prove_account() {
+        let bc = &mut Blockchain::new();
+
+        let account_id_satoshi = "satoshi".to_string();
+        let (keypair_satoshi, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+        let (_, tx_create_alice) = create_account_tx("alice".to_string());
+        assert!(
+            append_block_with_tx(bc, vec![
+                tx_create_satoshi,
+                mint_initial_supply(account_id_satoshi.clone(), 1_000),
+                tx_create_alice,
+            ]).is_ok()
+        );
+        let genesis = bc.blocks().tip().unwrap().header().clone();
+
+        let proof = bc.prove_account(account_id_satoshi.clone());
+        assert!(proof.verify(&genesis));
+        let account = proof.account.unwrap();
+        assert_eq!(account.balance(), 1_000);
+        assert_eq!(account.public_keys(), [*keypair_satoshi.public.as_bytes()]);
+
+        let proof = bc.prove_account("bob".to_string());
+        assert!(proof.account.is_none());
+        assert!(proof.verify(&genesis));
+
+        let mut tx = create_transfer_tx(account_id_satoshi.clone(), "alice".to_string(), 100, 0, 0);
+        tx.sign(&keypair_satoshi);
+        assert!(append_block_with_tx(bc, vec![tx]).is_ok());
+        let tip = bc.blocks().tip().unwrap().header().clone();
+
+        let proof = bc.prove_account(account_id_satoshi);
+        assert!(!proof.verify(&genesis));
+        assert!(proof.verify(&tip));
+        assert_eq!(proof.account.unwrap().balance(), 900);
+    }
+
+    #[test]
+    fn test_build_block_template() {
+        let bc = &mut Blockchain::with_params(ChainParams {
+            max_block_transactions: 3,
+            ..Default::default()
+        });
+
+        let account_id_satoshi = "satoshi".to_string();
+        let (keypair_satoshi, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+        let tx_mint_satoshi = mint_initial_supply(account_id_satoshi.clone(), 1_500);
+
+        let account_id_alice = "alice".to_string();
+        let (keypair_alice, tx_create_alice) = create_account_tx(account_id_alice.clone());
+
+        assert!(
+            append_block_with_tx(bc, vec![
+                tx_create_satoshi,
+                tx_mint_satoshi,
+                tx_create_alice,
+            ]).is_ok()
+        );
+
+        let mut tx_tr_from_satoshi_to_alice = create_transfer_tx(
+            account_id_satoshi.clone(),
+            account_id_alice.clone(),
+            500,
+            0,
+            0,
+        );
+        tx_tr_from_satoshi_to_alice.sign(&keypair_satoshi);
+        assert!(append_block_with_tx(bc, vec![tx_tr_from_satoshi_to_alice]).is_ok());
+
+        let mut txs = vec![];
+        for (nonce, fee) in [(1, 5), (2, 50)] {
+            let mut tx = create_transfer_tx(
+                account_id_satoshi.clone(),
+                account_id_alice.clone(),
+                100,
+                fee,
+                nonce,
+            );
+            tx.sign(&keypair_satoshi);
+            txs.push(tx);
+        }
+        let mut tx = create_transfer_tx(
+            account_id_alice.clone(),
+            account_id_satoshi.clone(),
+            100,
+            20,
+            0,
+        );
+        tx.sign(&keypair_alice);
+        txs.push(tx);
+
+        for tx in txs.iter() {
+            assert!(bc.submit_transaction(tx.clone()).is_ok());
+        }
+
+        let mut block = bc.build_block_template(account_id_satoshi.clone()).unwrap();
+        let hashes: Vec<_> = block.transactions.iter().skip(1).map(|tx| tx.hash()).collect();
+        assert_eq!(hashes, vec![txs[2].hash(), txs[0].hash()]);
+        assert_eq!(block.fees(), 25);
+
+        block.mine(bc.target).unwrap();
+        assert!(bc.append_block(block).is_ok());
+        assert_eq!(bc.transaction_pool().len(), 1);
+
+        let mut block = bc.build_block_template(account_id_satoshi.clone()).unwrap();
+        assert_eq!(block.transactions.len(), 2);
+        assert_eq!(block.transactions[1].hash(), txs[1].hash());
+
+        block.mine(bc.target).unwrap();
+        assert!(bc.append_block(block).is_ok());
+        assert!(bc.transaction_pool().is_empty());
+        assert_eq!(
+            bc.build_block_template("carol".to_string()).err().unwrap(),
+            BlockError::InvalidBeneficiary { account_id: "carol".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_persistence() {
+        let path = std::env::temp_dir().join(format!("blockchain_workshop_{}", generate_account_id()));
+
+        let account_id_satoshi = "satoshi".to_string();
+        let account_id_alice = "alice".to_string();
+        {
+            let bc = &mut Blockchain::open(&path).unwrap();
+            assert!(bc.is_empty());
+
+            let (keypair_satoshi, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+            let (_, tx_create_alice) = create_account_tx(account_id_alice.clone());
+            assert!(
+                append_block_with_tx(bc, vec![
+                    tx_create_satoshi,
+                    mint_initial_supply(account_id_satoshi.clone(), 100_000_000),
+                    tx_create_alice,
+                ]).is_ok()
+            );
+
+            let mut tx = create_transfer_tx(
+                account_id_satoshi.clone(),
+                account_id_alice.clone(),
+                1_000,
+                10,
+                0,
+            );
+            tx.sign(&keypair_satoshi);
+            assert!(append_block_with_tx(bc, vec![tx]).is_ok());
+            append_block(bc);
+        }
+
+        let bc = Blockchain::open(&path).unwrap();
+        assert_eq!(bc.len(), 3);
+        assert!(bc.validate().is_ok());
+        let satoshi = bc.get_account_by_id(account_id_satoshi).unwrap();
+        assert_eq!(satoshi.balance(), 100_000_000 - 1_010);
+        assert_eq!(satoshi.nonce(), 1);
+        assert_eq!(bc.get_account_by_id(account_id_alice).unwrap().balance(), 1_000);
+
+        let last_hash = bc.get_last_block_hash();
+        drop(bc);
+        let bc = &mut Blockchain::open(&path).unwrap();
+        assert_eq!(bc.get_last_block_hash(), last_hash);
+        append_block(bc);
+        assert_eq!(Blockchain::open(&path).unwrap().len(), 4);
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    fn mine_on(bc: &Blockchain, prev_hash: Hash, transactions: Vec<Transaction>) -> Block {
+        let mut block = Block::new(Some(prev_hash.clone()));
+        for tx in transactions {
+            block.add_transaction(tx);
+        }
+        if block.transactions.is_empty() {
+            block.add_transaction(create_account_tx(generate_account_id()).1);
+        }
+        let target = match replay_branch(bc, prev_hash) {
+            Ok(mut replay) => {
+                block.set_timestamp(block.timestamp().max(replay.median_time_past() + 1));
+                if let Ok(state_root) = replay.state_root_after(&block) {
+                    block.set_state_root(state_root);
+                }
+                replay.target()
+            }
+            Err(_) => bc.target,
+        };
+        block.mine(target).unwrap();
+        block
+    }
+
+    // Replays the branch ending at `prev_hash` to build on a block that
+    // doesn't extend the active tip.
+    fn replay_branch(bc: &Blockchain, prev_hash: Hash) -> Result<Blockchain, BlockError> {
+        let mut branch = vec![];
+        let mut next = Some(prev_hash);
+        while let Some(hash) = next {
+            let ancestor = bc.blocks.get(&hash).unwrap().clone();
+            next = ancestor.prev_hash().cloned();
+            branch.push(ancestor);
+        }
+
+        let mut replay = Blockchain::with_params(bc.params.clone());
+        for ancestor in branch.into_iter().rev() {
+            replay.append_block(ancestor)?;
+        }
+        Ok(replay)
+    }
+
+    #[test]
+    fn test_reorg() {
+        let bc = &mut Blockchain::new();
+
+        let account_id_satoshi = "satoshi".to_string();
+        let (keypair_satoshi, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+        let account_id_alice = "alice".to_string();
+        let (_, tx_create_alice) = create_account_tx(account_id_alice.clone());
+        assert!(
+            append_block_with_tx(bc, vec![
+                tx_create_satoshi,
+                mint_initial_supply(account_id_satoshi.clone(), 100_000_000),
+                tx_create_alice,
+            ]).is_ok()
+        );
+        let genesis_hash = bc.get_last_block_hash().unwrap();
+
+        let mut tx_transfer = create_transfer_tx(
+            account_id_satoshi.clone(),
+            account_id_alice.clone(),
+            1_000,
+            10,
+            0,
+        );
+        tx_transfer.sign(&keypair_satoshi);
+        let a1 = mine_on(bc, genesis_hash.clone(), vec![tx_transfer.clone()]);
+        assert_eq!(bc.append_block(a1.clone()), Ok(ChainUpdate::Extended));
+        assert_eq!(bc.get_account_by_id(account_id_alice.clone()).unwrap().balance(), 1_000);
+
+        let b1 = mine_on(bc, genesis_hash.clone(), vec![]);
+        assert_eq!(bc.append_block(b1.clone()), Ok(ChainUpdate::SideBranch));
+        assert_eq!(bc.get_last_block_hash(), Some(a1.hash()));
+        assert_eq!(
+            bc.append_block(b1.clone()).err().unwrap(),
+            BlockError::AlreadyKnown
+        );
+
+        let b2 = mine_on(bc, b1.hash(), vec![]);
+        assert_eq!(
+            bc.append_block(b2.clone()),
+            Ok(ChainUpdate::Reorg {
+                disconnected: vec![a1.clone()],
+                connected: vec![b1.clone(), b2.clone()],
+            })
+        );
+        assert_eq!(bc.len(), 3);
+        assert_eq!(bc.blocks().block_count(), 4);
+        assert_eq!(bc.get_last_block_hash(), Some(b2.hash()));
+        assert!(bc.validate().is_ok());
+
+        let satoshi = bc.get_account_by_id(account_id_satoshi.clone()).unwrap();
+        assert_eq!(satoshi.balance(), 100_000_000);
+        assert_eq!(satoshi.nonce(), 0);
+        assert_eq!(bc.get_account_by_id(account_id_alice.clone()).unwrap().balance(), 0);
+        assert!(bc.transaction_pool().contains(&tx_transfer.hash()));
+
+        let a2 = mine_on(bc, a1.hash(), vec![]);
+        assert_eq!(bc.append_block(a2.clone()), Ok(ChainUpdate::SideBranch));
+        let a3 = mine_on(bc, a2.hash(), vec![]);
+        assert_eq!(
+            bc.append_block(a3.clone()),
+            Ok(ChainUpdate::Reorg {
+                disconnected: vec![b2, b1],
+                connected: vec![a1, a2, a3.clone()],
+            })
+        );
+        assert_eq!(bc.get_last_block_hash(), Some(a3.hash()));
+        assert_eq!(bc.get_account_by_id(account_id_alice).unwrap().balance(), 1_000);
+        assert!(!bc.transaction_pool().contains(&tx_transfer.hash()));
+        assert_eq!(bc.state_root(), StateTree::from_accounts(&bc.accounts).root());
+    }
+
+    #[test]
+    fn test_reorg_to_invalid_branch() {
+        let bc = &mut Blockchain::new();
+
+        let account_id_satoshi = "satoshi".to_string();
+        let (_, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+        assert!(
+            append_block_with_tx(bc, vec![
+                tx_create_satoshi,
+                mint_initial_supply(account_id_satoshi.clone(), 100_000_000),
+            ]).is_ok()
+        );
+        let genesis_hash = bc.get_last_block_hash().unwrap();
+        let a1 = append_block(bc);
+
+        let b1 = mine_on(bc, genesis_hash, vec![]);
+        assert_eq!(bc.append_block(b1.clone()), Ok(ChainUpdate::SideBranch));
+        let state_root = bc.state_root();
+        let b2 = mine_on(bc, b1.hash(), vec![create_coinbase_tx(account_id_satoshi.clone(), 1)]);
+        assert_eq!(
+            bc.append_block(b2.clone()).err().unwrap(),
+            BlockError::InvalidCoinbaseAmount {
+                expected: bc.next_block_subsidy(),
+                got: 1,
+            }
+        );
+
+        assert_eq!(bc.get_last_block_hash(), Some(a1.hash()));
+        assert!(!bc.blocks().contains(&b2.hash()));
+        assert!(bc.blocks().contains(&b1.hash()));
+        assert_eq!(bc.state_root(), state_root);
+        assert!(bc.validate().is_ok());
+
+        append_block(bc);
+        assert!(bc.validate_full().is_ok());
+    }
+
+    #[test]
+    fn test_side_branch_checks() {
+        let bc = &mut Blockchain::with_params(ChainParams {
+            max_side_blocks: 2,
+            ..Default::default()
+        });
+        let genesis = append_block(bc);
+        append_block(bc);
+        append_block(bc);
+
+        let mut block = mine_on(bc, genesis.hash(), vec![]);
+        block.mine(U256::from_compact(0x2000ffff).unwrap()).unwrap();
+        assert_eq!(
+            bc.append_block(block.clone()).err().unwrap(),
+            BlockError::InvalidBits {
+                expected: bc.target.to_compact(),
+                got: 0x2000ffff,
+            }
+        );
+
+        block.set_timestamp(genesis.timestamp());
+        block.mine(bc.target).unwrap();
+        assert_eq!(
+            bc.append_block(block.clone()).err().unwrap(),
+            BlockError::TimestampBeforeMedian {
+                timestamp: genesis.timestamp(),
+                median_time_past: genesis.timestamp(),
+            }
+        );
+        assert!(!bc.blocks().contains(&block.hash()));
+
+        for _ in 0..3 {
+            let side = mine_on(bc, genesis.hash(), vec![]);
+            assert_eq!(bc.append_block(side), Ok(ChainUpdate::SideBranch));
+        }
+        assert_eq!(bc.blocks().side_block_count(), 2);
+        assert_eq!(bc.blocks().block_count(), 5);
+    }
+
+    #[test]
+    fn test_reorg_persistence() {
+        let path = std::env::temp_dir().join(format!("blockchain_workshop_{}", generate_account_id()));
+
+        let tip = {
+            let bc = &mut Blockchain::open(&path).unwrap();
+            append_block(bc);
+            let fork_hash = bc.get_last_block_hash().unwrap();
+            append_block(bc);
+
+            let b1 = mine_on(bc, fork_hash, vec![]);
+            assert_eq!(bc.append_block(b1.clone()), Ok(ChainUpdate::SideBranch));
+            let b2 = mine_on(bc, b1.hash(), vec![]);
+            assert!(matches!(bc.append_block(b2.clone()), Ok(ChainUpdate::Reorg { .. })));
+            b2.hash()
+        };
+
+        let bc = Blockchain::open(&path).unwrap();
+        assert_eq!(bc.len(), 3);
+        assert_eq!(bc.get_last_block_hash(), Some(tip));
+        assert_eq!(bc.blocks().block_count(), 4);
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_validate_full() {
+        let bc = &mut Blockchain::new();
+
+        let account_id_satoshi = "satoshi".to_string();
+        let (keypair_satoshi, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+        let account_id_alice = "alice".to_string();
+        let (keypair_alice, tx_create_alice) = create_account_tx(account_id_alice.clone());
+        assert!(
+            append_block_with_tx(bc, vec![
+                tx_create_satoshi,
+                mint_initial_supply(account_id_satoshi.clone(), 100_000_000),
+                tx_create_alice,
+            ]).is_ok()
+        );
+
+        let mut tx = create_transfer_tx(
+            account_id_satoshi.clone(),
+            account_id_alice.clone(),
+            1_000,
+            10,
+            0,
+        );
+        tx.sign(&keypair_satoshi);
+        assert!(append_block_with_tx(bc, vec![tx]).is_ok());
+        append_block(bc);
+
+        assert!(bc.validate_full().is_ok());
+        let snapshot = bc.snapshot();
+        assert_eq!(snapshot.len(), 3);
+        assert!(snapshot.validate_full().is_ok());
+
+        bc.accounts.get_mut(&account_id_alice).unwrap().balance += 1;
+        assert_eq!(bc.snapshot().validate_full(), bc.validate_full());
+        assert_eq!(
+            bc.validate_full().err().unwrap(),
+            ValidationError::AccountMismatch {
+                account_id: account_id_alice.clone(),
+            }
+        );
+        bc.accounts.get_mut(&account_id_alice).unwrap().balance -= 1;
+
+        bc.blocks.get_by_height_mut(1).unwrap().transactions[0].sign(&keypair_alice);
+        assert_eq!(bc.validate().err().unwrap(), ValidationError::InvalidHash { height: 1 });
+        assert_eq!(
+            bc.validate_full().err().unwrap(),
+            ValidationError::InvalidBlock {
+                height: 1,
+                error: BlockError::InvalidHash,
+            }
+        );
+    }
+
+    #[test]
+    fn test_difficulty_algorithm() {
+        let algorithm = Asert {
+            half_life: 8,
+            ..Default::default()
+        };
+        let max_target = algorithm.max_target;
+        let bc = &mut Blockchain::with_params(ChainParams {
+            difficulty_algorithm: std::sync::Arc::new(algorithm),
+            ..Default::default()
+        });
+        assert_eq!(bc.target(), max_target);
+
+        for _ in 0..4 {
+            append_block(bc);
+        }
+        assert!(bc.target() < max_target);
+        assert!(bc.difficulty() > 1.);
+
+        let mut block = new_block(bc);
+        block.add_transaction(create_account_tx(generate_account_id()).1);
+        block.mine(max_target).unwrap();
+        assert_eq!(
+            bc.append_block(block).err().unwrap(),
+            BlockError::InvalidBits {
+                expected: bc.target().to_compact(),
+                got: max_target.to_compact(),
+            }
+        );
+        assert!(bc.validate_full().is_ok());
+    }
+
+    #[test]
+    fn test_timestamp_rules() {
+        let bc = &mut Blockchain::new();
+
+        let account_id_satoshi = "satoshi".to_string();
+        let (_, tx_create_satoshi) = create_account_tx(account_id_satoshi.clone());
+        assert!(append_block_with_tx(bc, vec![tx_create_satoshi]).is_ok());
+
+        let mut block = new_block(bc);
+        block.add_transaction(create_account_tx(generate_account_id()).1);
+        let timestamp = get_timestamp() + MAX_FUTURE_BLOCK_TIME + 60;
+        block.set_timestamp(timestamp);
+        block.mine(bc.target).unwrap();
+        assert!(matches!(
+            bc.append_block(block),
+            Err(BlockError::TimestampTooFarInFuture { timestamp: t, .. }) if t == timestamp
+        ));
+
+        let start = get_timestamp() + 100;
+        for i in 0..5 {
+            let mut block = new_block(bc);
+            block.add_transaction(create_account_tx(generate_account_id()).1);
+            block.set_timestamp(start + i * 10);
+            block.set_state_root(bc.state_root_after(&block).unwrap());
+            block.mine(bc.target).unwrap();
+            assert!(bc.append_block(block).is_ok());
+        }
+        assert_eq!(bc.median_time_past(), start + 20);
+
+        let mut block = new_block(bc);
+        block.add_transaction(create_account_tx(generate_account_id()).1);
+        block.set_timestamp(start + 19);
+        block.set_state_root(bc.state_root_after(&block).unwrap());
+        block.mine(bc.target).unwrap();
+        assert_eq!(
+            bc.append_block(block.clone()).err().unwrap(),
+            BlockError::TimestampBeforeMedian {
+                timestamp: start + 19,
+                median_time_past: start + 20,
+            }
+        );
+
+        block.set_timestamp(start + 20);
+        block.mine(bc.target).unwrap();
+        assert_eq!(
+            bc.append_block(block.clone()).err().unwrap(),
+            BlockError::TimestampBeforeMedian {
+                timestamp: start + 20,
+                median_time_past: start + 20,
+            }
+        );
+
+        block.set_timestamp(start + 21);
+        block.mine(bc.target).unwrap();
+        assert!(bc.append_block(block).is_ok());
+
+        let template = bc.build_block_template(account_id_satoshi).unwrap();
+        assert_eq!(template.timestamp(), start + 21);
+    }
 }
@@ -59,6 +59,10 @@ impl<T: Default> Chain<T> {
         self.len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     pub fn head(&self) -> Option<&T> {
         match &self.head {
             None => None,
@@ -66,13 +70,13 @@ impl<T: Default> Chain<T> {
         }
     }
 
-    pub fn iter(&self) -> ChainIter<T> {
+    pub fn iter(&self) -> ChainIter<'_, T> {
         ChainIter {
             next: self.head.as_deref(),
         }
     }
 
-    pub fn iter_mut(&mut self) -> ChainIterMut<T> {
+    pub fn iter_mut(&mut self) -> ChainIterMut<'_, T> {
         ChainIterMut {
             next: self.head.as_deref_mut(),
         }
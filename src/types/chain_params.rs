@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use crate::difficulty::EpochRetarget;
+use crate::traits::DifficultyAlgorithm;
+use crate::types::{
+    Balance, COINBASE_MATURITY, HALVING_INTERVAL, INITIAL_SUBSIDY, MAX_BLOCK_TRANSACTIONS,
+    MAX_FUTURE_BLOCK_TIME, MAX_POOL_SIZE, MAX_POOL_TXS_PER_SENDER, MAX_SIDE_BLOCKS, MAX_SUPPLY, Timestamp,
+};
+
+#[derive(Debug, Clone)]
+pub struct ChainParams {
+    pub initial_subsidy: Balance,
+    pub halving_interval: usize,
+    pub max_supply: Balance,
+    pub coinbase_maturity: usize,
+    pub max_block_transactions: usize,
+    pub max_pool_size: usize,
+    pub max_pool_txs_per_sender: usize,
+    pub max_future_block_time: Timestamp,
+    pub max_side_blocks: usize,
+    pub difficulty_algorithm: Arc<dyn DifficultyAlgorithm>,
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        Self {
+            initial_subsidy: INITIAL_SUBSIDY,
+            halving_interval: HALVING_INTERVAL,
+            max_supply: MAX_SUPPLY,
+            coinbase_maturity: COINBASE_MATURITY,
+            max_block_transactions: MAX_BLOCK_TRANSACTIONS,
+            max_pool_size: MAX_POOL_SIZE,
+            max_pool_txs_per_sender: MAX_POOL_TXS_PER_SENDER,
+            max_future_block_time: MAX_FUTURE_BLOCK_TIME,
+            max_side_blocks: MAX_SIDE_BLOCKS,
+            difficulty_algorithm: Arc::new(EpochRetarget::default()),
+        }
+    }
+}
+
+impl ChainParams {
+    pub fn block_subsidy(&self, height: usize) -> Balance {
+        if self.halving_interval == 0 {
+            return self.initial_subsidy;
+        }
+
+        let halvings = height / self.halving_interval;
+        if halvings >= Balance::BITS as usize {
+            return 0;
+        }
+        self.initial_subsidy >> halvings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_subsidy() {
+        let params = ChainParams {
+            initial_subsidy: 100,
+            halving_interval: 10,
+            ..Default::default()
+        };
+
+        assert_eq!(params.block_subsidy(0), 100);
+        assert_eq!(params.block_subsidy(9), 100);
+        assert_eq!(params.block_subsidy(10), 50);
+        assert_eq!(params.block_subsidy(25), 25);
+        assert_eq!(params.block_subsidy(70), 0);
+        assert_eq!(params.block_subsidy(usize::MAX), 0);
+    }
+}
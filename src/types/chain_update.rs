@@ -0,0 +1,13 @@
+use crate::types::Block;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainUpdate {
+    Extended,
+    SideBranch,
+    // `disconnected` runs from the old tip down to the fork point,
+    // `connected` from the fork point up to the new tip.
+    Reorg {
+        disconnected: Vec<Block>,
+        connected: Vec<Block>,
+    },
+}
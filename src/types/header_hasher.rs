@@ -0,0 +1,61 @@
+use blake2::{Blake2s, Digest};
+use blake2::digest::FixedOutput;
+
+use crate::encoding::ENCODING_VERSION;
+use crate::types::{BlockHeader, Target, U256};
+
+// Blake2s state after absorbing every header field but the nonce, which is
+// encoded last so that each attempt only has to hash the final 16 bytes.
+#[derive(Debug, Clone)]
+pub struct HeaderHasher {
+    midstate: Blake2s,
+}
+
+impl HeaderHasher {
+    pub fn new(header: &BlockHeader) -> Self {
+        let mut prefix = vec![ENCODING_VERSION];
+        header.encode_without_nonce(&mut prefix);
+
+        let mut midstate = Blake2s::new();
+        midstate.update(&prefix);
+        Self { midstate }
+    }
+
+    pub fn hash(&self, nonce: u128) -> [u8; 32] {
+        let mut hasher = self.midstate.clone();
+        hasher.update(nonce.to_be_bytes());
+        hasher.finalize_fixed().into()
+    }
+
+    pub fn meets_target(&self, nonce: u128, target: &Target) -> bool {
+        U256::from_be_bytes(self.hash(nonce)) <= *target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::Hashable;
+    use crate::types::MAX_TARGET;
+
+    #[test]
+    fn test_matches_header_hash() {
+        let mut header = BlockHeader {
+            version: 1,
+            prev_hash: Some("00".repeat(32)),
+            merkle_root: "ab".repeat(32),
+            state_root: "cd".repeat(32),
+            timestamp: 1_600_000_000,
+            bits: MAX_TARGET,
+            extra_nonce: 7,
+            nonce: 0,
+        };
+        let hasher = HeaderHasher::new(&header);
+
+        for nonce in [0, 1, 42, u128::MAX].iter().copied() {
+            header.nonce = nonce;
+            assert_eq!(hex::encode(hasher.hash(nonce)), header.hash());
+            assert_eq!(hasher.meets_target(nonce, &header.target().unwrap()), header.meets_target());
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+use std::io;
+
+use crate::types::{AccountId, Error};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeystoreError {
+    Io { kind: io::ErrorKind, message: String },
+    InvalidAccountId { account_id: AccountId },
+    UnknownAccount { account_id: AccountId },
+    AccountExists { account_id: AccountId },
+    WrongPassword { account_id: AccountId },
+    InvalidKey,
+    InvalidParams { message: String },
+    Decode { account_id: AccountId, error: Error },
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::Io { message, .. } => write!(f, "Keystore error: {}", message),
+            KeystoreError::InvalidAccountId { account_id } => {
+                write!(f, "Invalid account id: {}", account_id)
+            }
+            KeystoreError::UnknownAccount { account_id } => {
+                write!(f, "No key stored for account {}.", account_id)
+            }
+            KeystoreError::AccountExists { account_id } => {
+                write!(f, "Key for account {} already exists.", account_id)
+            }
+            KeystoreError::WrongPassword { account_id } => {
+                write!(f, "Wrong password for account {}.", account_id)
+            }
+            KeystoreError::InvalidKey => write!(f, "Invalid secret key."),
+            KeystoreError::InvalidParams { message } => {
+                write!(f, "Invalid key derivation parameters: {}", message)
+            }
+            KeystoreError::Decode { account_id, error } => {
+                write!(f, "Can't decode key file for account {}: {}", account_id, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+impl From<io::Error> for KeystoreError {
+    fn from(error: io::Error) -> Self {
+        KeystoreError::Io {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
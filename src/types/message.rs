@@ -0,0 +1,125 @@
+use crate::encoding::{invalid_tag, Reader};
+use crate::traits::{Decode, Encode};
+use crate::types::{Block, BlockHeader, Error, Hash, Transaction};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Version { version: u32, genesis: Option<Hash>, height: u64 },
+    VerAck,
+    GetHeaders { locator: Vec<Hash> },
+    Headers(Vec<BlockHeader>),
+    Inventory { blocks: Vec<Hash>, transactions: Vec<Hash> },
+    GetData { blocks: Vec<Hash>, transactions: Vec<Hash> },
+    Block(Block),
+    Transaction(Transaction),
+}
+
+impl Encode for Message {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Message::Version { version, genesis, height } => {
+                out.push(0);
+                version.encode(out);
+                genesis.encode(out);
+                height.encode(out);
+            }
+            Message::VerAck => out.push(1),
+            Message::GetHeaders { locator } => {
+                out.push(2);
+                locator.encode(out);
+            }
+            Message::Headers(headers) => {
+                out.push(3);
+                headers.encode(out);
+            }
+            Message::Inventory { blocks, transactions } => {
+                out.push(4);
+                blocks.encode(out);
+                transactions.encode(out);
+            }
+            Message::GetData { blocks, transactions } => {
+                out.push(5);
+                blocks.encode(out);
+                transactions.encode(out);
+            }
+            Message::Block(block) => {
+                out.push(6);
+                block.encode(out);
+            }
+            Message::Transaction(tx) => {
+                out.push(7);
+                tx.encode(out);
+            }
+        }
+    }
+}
+
+impl Decode for Message {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        match reader.read_tag()? {
+            0 => Ok(Message::Version {
+                version: u32::decode(reader)?,
+                genesis: Option::<Hash>::decode(reader)?,
+                height: u64::decode(reader)?,
+            }),
+            1 => Ok(Message::VerAck),
+            2 => Ok(Message::GetHeaders {
+                locator: Vec::<Hash>::decode(reader)?,
+            }),
+            3 => Ok(Message::Headers(Vec::<BlockHeader>::decode(reader)?)),
+            4 => Ok(Message::Inventory {
+                blocks: Vec::<Hash>::decode(reader)?,
+                transactions: Vec::<Hash>::decode(reader)?,
+            }),
+            5 => Ok(Message::GetData {
+                blocks: Vec::<Hash>::decode(reader)?,
+                transactions: Vec::<Hash>::decode(reader)?,
+            }),
+            6 => Ok(Message::Block(Block::decode(reader)?)),
+            7 => Ok(Message::Transaction(Transaction::decode(reader)?)),
+            tag => Err(invalid_tag(tag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::traits::Hashable;
+    use crate::utils::create_account_tx;
+
+    use super::*;
+
+    #[test]
+    fn test_encoding() {
+        let mut block = Block::new(None);
+        let (_, tx) = create_account_tx("alice".to_string());
+        block.add_transaction(tx.clone());
+
+        let messages = vec![
+            Message::Version {
+                version: 1,
+                genesis: Some(block.hash()),
+                height: 3,
+            },
+            Message::VerAck,
+            Message::GetHeaders {
+                locator: vec![block.hash()],
+            },
+            Message::Headers(vec![block.header().clone()]),
+            Message::Inventory {
+                blocks: vec![block.hash()],
+                transactions: vec![tx.hash()],
+            },
+            Message::GetData {
+                blocks: vec![],
+                transactions: vec![tx.hash()],
+            },
+            Message::Block(block),
+            Message::Transaction(tx),
+        ];
+        for message in messages {
+            assert_eq!(Message::from_bytes(&message.to_bytes()), Ok(message));
+        }
+        assert_eq!(Message::from_bytes(&[1, 8]), Err("Invalid tag 8.".to_string()));
+    }
+}
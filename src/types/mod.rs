@@ -2,15 +2,45 @@ use ed25519_dalek::{PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH, SIGNATURE_LENGTH};
 
 pub use account::{Account, AccountType};
 pub use block::Block;
-pub use blockchain::Blockchain;
+pub use block_error::BlockError;
+pub use block_header::BlockHeader;
+pub use block_tree::BlockTree;
+pub use blockchain::{Blockchain, ChainSnapshot, SharedChain};
 pub use chain::Chain;
+pub use chain_params::ChainParams;
+pub use chain_update::ChainUpdate;
+pub use header_hasher::HeaderHasher;
+pub use keystore_error::KeystoreError;
+pub use message::Message;
+pub use pool_error::PoolError;
+pub use rpc_error::RpcError;
+pub use storage_error::StorageError;
 pub use transaction::{Transaction, TransactionData};
+pub use transaction_pool::TransactionPool;
+pub use tx_error::TxError;
+pub use u256::U256;
+pub use validation_error::ValidationError;
 
 mod account;
 mod block;
+mod block_error;
+mod block_header;
+mod block_tree;
 mod blockchain;
 mod chain;
+mod chain_params;
+mod chain_update;
+mod header_hasher;
+mod keystore_error;
+mod message;
+mod pool_error;
+mod rpc_error;
+mod storage_error;
 mod transaction;
+mod transaction_pool;
+mod tx_error;
+mod u256;
+mod validation_error;
 
 pub type Hash = String;
 pub type Timestamp = u64;
@@ -20,10 +50,24 @@ pub type Error = String;
 pub type PublicKeyBytes = [u8; PUBLIC_KEY_LENGTH];
 pub type SecretKeyBytes = [u8; SECRET_KEY_LENGTH];
 pub type SignatureBytes = [u8; SIGNATURE_LENGTH];
-pub type Target = String;
+pub type Target = U256;
 pub type Bits = i32;
 pub type Difficulty = f32;
+pub type Work = U256;
 
-
-pub const MAX_TARGET: Bits = 0x1effffff;
-pub const EXPECTED_TIME: i32 = 4;
\ No newline at end of file
+pub const BLOCK_VERSION: u32 = 1;
+pub const MAX_TARGET: Bits = 0x1f00ffff;
+pub const EXPECTED_TIME: i32 = 4;
+pub const DIFFICULTY_ADJUSTMENT_INTERVAL: usize = 16;
+pub const INITIAL_SUBSIDY: Balance = 50_000_000;
+pub const HALVING_INTERVAL: usize = 210_000;
+pub const MAX_SUPPLY: Balance = 21_000_000_000_000_000;
+pub const COINBASE_MATURITY: usize = 100;
+pub const MAX_BLOCK_TRANSACTIONS: usize = 1_000;
+pub const MAX_POOL_SIZE: usize = 5_000;
+pub const MAX_POOL_TXS_PER_SENDER: usize = 16;
+pub const MAX_MULTISIG_KEYS: usize = 16;
+pub const MEDIAN_TIME_SPAN: usize = 11;
+pub const MAX_FUTURE_BLOCK_TIME: Timestamp = 2 * 60 * 60;
+pub const MAX_NONCE: u128 = u32::MAX as u128;
+pub const MAX_SIDE_BLOCKS: usize = 1_000;
@@ -0,0 +1,68 @@
+use std::fmt;
+
+use crate::types::{AccountId, TxError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PoolError {
+    AlreadyInPool,
+    NotPoolable,
+    NoncePending { account_id: AccountId, nonce: u128 },
+    TooManyFromSender { account_id: AccountId, max: usize },
+    PoolFull,
+    Transaction(TxError),
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::AlreadyInPool => write!(f, "Transaction already in pool."),
+            PoolError::NotPoolable => write!(f, "Transaction can't be submitted to pool."),
+            PoolError::NoncePending { account_id, nonce } => {
+                write!(f, "Nonce {} already pending for {}.", nonce, account_id)
+            }
+            PoolError::TooManyFromSender { account_id, max } => write!(
+                f,
+                "Too many pending transactions from {}: at most {}.",
+                account_id, max
+            ),
+            PoolError::PoolFull => write!(f, "Transaction pool is full."),
+            PoolError::Transaction(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PoolError::Transaction(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<TxError> for PoolError {
+    fn from(error: TxError) -> Self {
+        PoolError::Transaction(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let error = PoolError::from(TxError::InvalidSignature);
+        assert_eq!(error.to_string(), "Signature invalid.");
+        assert_eq!(error.source().unwrap().to_string(), "Signature invalid.");
+
+        let error = PoolError::TooManyFromSender {
+            account_id: "alice".to_string(),
+            max: 16,
+        };
+        assert_eq!(error.to_string(), "Too many pending transactions from alice: at most 16.");
+        assert!(error.source().is_none());
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+
+use crate::types::{AccountId, PoolError, ValidationError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcError {
+    ParseError,
+    InvalidRequest,
+    MethodNotFound { method: String },
+    InvalidParams { message: String },
+    Internal { message: String },
+    UnknownAccount { account_id: AccountId },
+    UnknownBlock,
+    TransactionRejected { error: PoolError },
+    InvalidChain { error: ValidationError },
+}
+
+impl RpcError {
+    pub fn code(&self) -> i64 {
+        match self {
+            RpcError::ParseError => -32700,
+            RpcError::InvalidRequest => -32600,
+            RpcError::MethodNotFound { .. } => -32601,
+            RpcError::InvalidParams { .. } => -32602,
+            RpcError::Internal { .. } => -32603,
+            RpcError::UnknownAccount { .. } => -32001,
+            RpcError::UnknownBlock => -32002,
+            RpcError::TransactionRejected { .. } => -32003,
+            RpcError::InvalidChain { .. } => -32004,
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::ParseError => write!(f, "Parse error."),
+            RpcError::InvalidRequest => write!(f, "Invalid request."),
+            RpcError::MethodNotFound { method } => write!(f, "Method not found: {}", method),
+            RpcError::InvalidParams { message } => write!(f, "Invalid params: {}", message),
+            RpcError::Internal { message } => write!(f, "Internal error: {}", message),
+            RpcError::UnknownAccount { account_id } => write!(f, "Unknown account {}.", account_id),
+            RpcError::UnknownBlock => write!(f, "Unknown block."),
+            RpcError::TransactionRejected { error } => {
+                write!(f, "Transaction rejected: {}", error)
+            }
+            RpcError::InvalidChain { error } => write!(f, "Chain is invalid: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RpcError::TransactionRejected { error } => Some(error),
+            RpcError::InvalidChain { error } => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use std::fmt;
+use std::io;
+
+use crate::types::{BlockError, Error};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageError {
+    Io { kind: io::ErrorKind, message: String },
+    Corrupted { offset: u64 },
+    Decode { offset: u64, error: Error },
+    InvalidBlock { position: usize, error: Box<BlockError> },
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io { message, .. } => write!(f, "Storage error: {}", message),
+            StorageError::Corrupted { offset } => {
+                write!(f, "Corrupted block record at offset {}.", offset)
+            }
+            StorageError::Decode { offset, error } => {
+                write!(f, "Can't decode block record at offset {}: {}", offset, error)
+            }
+            StorageError::InvalidBlock { position, error } => {
+                write!(f, "Stored block {} is invalid: {}", position, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StorageError::InvalidBlock { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(error: io::Error) -> Self {
+        StorageError::Io {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
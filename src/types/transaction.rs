@@ -2,27 +2,34 @@ use blake2::{Blake2s, Digest};
 use blake2::digest::FixedOutput;
 use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
 
-use crate::traits::{Hashable, WorldState};
+use crate::encoding::{invalid_tag, Reader, ENCODING_VERSION};
+use crate::traits::{Decode, Encode, Hashable, WorldState};
 use crate::types::{
-    Account, AccountId, AccountType, Balance, Error, Hash, PublicKeyBytes, SignatureBytes,
-    Timestamp,
+    Account, AccountId, AccountType, Balance, Error, Hash, PublicKeyBytes,
+    SignatureBytes, Timestamp, TxError,
 };
 use crate::utils::get_timestamp;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Transaction {
-    nonce: u128,
+    pub(crate) nonce: u128,
     timestamp: Timestamp,
-    from: Option<AccountId>,
+    pub(crate) from: Option<AccountId>,
     pub(crate) data: TransactionData,
-    signature: Option<SignatureBytes>,
+    signatures: Vec<SignatureBytes>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TransactionData {
     CreateAccount(AccountId, PublicKeyBytes),
+    CreateMultisigAccount {
+        account_id: AccountId,
+        public_keys: Vec<PublicKeyBytes>,
+        threshold: u32,
+    },
     MintInitialSupply { to: AccountId, amount: Balance },
-    Transfer { to: AccountId, amount: Balance },
+    Transfer { to: AccountId, amount: Balance, fee: Balance },
+    Coinbase { to: AccountId, amount: Balance },
 }
 
 impl Transaction {
@@ -32,124 +39,404 @@ impl Transaction {
             timestamp: get_timestamp(),
             from,
             data,
-            signature: None,
+            signatures: vec![],
         }
     }
 
-    pub fn execute<T: WorldState>(&self, state: &mut T, is_genesis: bool) -> Result<(), Error> {
-        //TODO Task 2: Implement signature
+    pub fn set_nonce(&mut self, nonce: u128) {
+        self.nonce = nonce;
+    }
+
+    pub fn fee(&self) -> Balance {
+        match &self.data {
+            TransactionData::Transfer { fee, .. } => *fee,
+            _ => 0,
+        }
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        matches!(self.data, TransactionData::Coinbase { .. })
+    }
+
+    pub fn execute<T: WorldState>(&self, state: &mut T, is_genesis: bool) -> Result<(), TxError> {
         match &self.data {
             TransactionData::CreateAccount(account_id, public_key) => {
-                state.create_account(account_id.clone(), AccountType::User, public_key.clone())
+                state.create_account(account_id.clone(), Account::new(AccountType::User, *public_key))
+            }
+            TransactionData::CreateMultisigAccount {
+                account_id,
+                public_keys,
+                threshold,
+            } => {
+                Account::check_multisig(public_keys, *threshold)?;
+                state.create_account(
+                    account_id.clone(),
+                    Account::new_multisig(public_keys.clone(), *threshold),
+                )
             }
             TransactionData::MintInitialSupply { to, amount } => {
                 if !is_genesis {
-                    return Err("Initial supply can be minted only in genesis block.".to_string());
+                    return Err(TxError::MintOutsideGenesis);
                 }
                 match state.get_account_by_id_mut(to.clone()) {
                     Some(account) => {
                         account.balance += amount;
                         Ok(())
                     }
-                    None => Err("Invalid account.".to_string()),
+                    None => Err(TxError::InvalidAccount {
+                        account_id: to.clone(),
+                    }),
                 }
             }
-            // TODO Task 1: Implement transfer transition function
-            // 1. Check that receiver and sender accounts exist
-            // 2. Check sender balance
-            // 3. Change sender/receiver balances and save to state
-            // 4. Test
-            TransactionData::Transfer { to, amount } => {
-                if self.from.is_none() {
-                    return Err("Invalid sender account id.".to_string());
+            TransactionData::Coinbase { to, amount } => {
+                match state.get_account_by_id_mut(to.clone()) {
+                    Some(account) => {
+                        account.balance = account
+                            .balance
+                            .checked_add(*amount)
+                            .ok_or(TxError::AmountOverflow)?;
+                        Ok(())
+                    }
+                    None => Err(TxError::InvalidBeneficiary {
+                        account_id: to.clone(),
+                    }),
                 }
-
-                let from = &self.from.as_ref().unwrap().clone();
+            }
+            TransactionData::Transfer { to, amount, fee } => {
+                let from = self.from.as_ref().ok_or(TxError::MissingSender)?;
 
                 if from.eq(to) {
-                    return Err("Transfer to yourself.".to_string());
+                    return Err(TxError::SelfTransfer);
                 }
 
                 let sender = state
                     .get_account_by_id(from.clone())
-                    .ok_or("Invalid sender account.".to_string())?;
+                    .ok_or_else(|| TxError::InvalidSender {
+                        account_id: from.clone(),
+                    })?;
 
                 let receiver = state
                     .get_account_by_id(to.to_string())
-                    .ok_or("Invalid receiver account.".to_string())?;
+                    .ok_or_else(|| TxError::InvalidReceiver {
+                        account_id: to.clone(),
+                    })?;
 
-                if sender.balance < *amount {
-                    return Err("Sender doesn't have enough currency.".to_string());
+                if self.nonce < sender.nonce {
+                    return Err(TxError::NonceUsed {
+                        expected: sender.nonce,
+                        got: self.nonce,
+                    });
                 }
 
-                if !self.verify(&sender.clone()) {
-                    return Err("Signature invalid.".to_string());
+                if self.nonce > sender.nonce {
+                    return Err(TxError::NonceGap {
+                        expected: sender.nonce,
+                        got: self.nonce,
+                    });
+                }
+
+                let total = amount.checked_add(*fee).ok_or(TxError::AmountOverflow)?;
+
+                if sender.balance < total {
+                    return Err(TxError::InsufficientBalance {
+                        account_id: from.clone(),
+                        required: total,
+                        available: sender.balance,
+                    });
+                }
+
+                if sender.balance - sender.locked < total {
+                    return Err(TxError::ImmatureBalance {
+                        account_id: from.clone(),
+                        required: total,
+                        available: sender.balance - sender.locked,
+                    });
+                }
+
+                if !self.verify(sender) {
+                    return Err(TxError::InvalidSignature);
                 }
 
                 let balance = receiver
                     .balance
                     .checked_add(*amount)
-                    .ok_or("Transfer amount overflow.".to_string())?;
+                    .ok_or(TxError::AmountOverflow)?;
 
-                match state.get_account_by_id_mut(from.clone()) {
-                    Some(sender) => {
-                        sender.balance -= *amount;
-                    }
-                    None => return Err("Invalid sender account.".to_string()),
+                if let Some(sender) = state.get_account_by_id_mut(from.clone()) {
+                    sender.balance -= total;
+                    sender.nonce += 1;
                 }
 
-                match state.get_account_by_id_mut(to.to_string()) {
-                    Some(receiver) => {
-                        receiver.balance = balance;
-                    }
-                    None => return Err("Invalid receiver account.".to_string()),
+                if let Some(receiver) = state.get_account_by_id_mut(to.to_string()) {
+                    receiver.balance = balance;
                 }
                 Ok(())
             }
         }
     }
 
+    fn encode_unsigned(&self, out: &mut Vec<u8>) {
+        self.nonce.encode(out);
+        self.timestamp.encode(out);
+        self.from.encode(out);
+        self.data.encode(out);
+    }
+
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut out = vec![ENCODING_VERSION];
+        self.encode_unsigned(&mut out);
+        out
+    }
+
+    // Every signature has to come from a different key of the sender, and at
+    // least `threshold` of them are needed.
     pub fn verify(&self, sender: &Account) -> bool {
-        match self.signature {
-            Some(signature) => {
-                let pub_key = PublicKey::from_bytes(sender.public_key.as_ref().clone());
-                if pub_key.is_ok() {
-                    return pub_key
-                        .unwrap()
-                        .verify(self.hash().as_bytes(), &Signature::from(signature))
-                        .is_ok();
-                }
+        if self.signatures.len() > sender.public_keys.len() {
+            return false;
+        }
+
+        let message = self.signing_bytes();
+        let mut signed = vec![false; sender.public_keys.len()];
+        for signature in &self.signatures {
+            let signer = (0..sender.public_keys.len()).find(|index| {
+                !signed[*index] && verify_signature(&sender.public_keys[*index], &message, signature)
+            });
+            match signer {
+                Some(index) => signed[index] = true,
+                None => return false,
             }
-            None => return false,
         }
-        false
+        self.signatures.len() >= sender.threshold as usize
+    }
+
+    pub fn signatures(&self) -> &[SignatureBytes] {
+        &self.signatures
     }
 
     pub fn set_sign(&mut self, signature: SignatureBytes) {
-        self.signature = Some(signature);
+        self.signatures = vec![signature];
+    }
+
+    pub fn add_signature(&mut self, signature: SignatureBytes) {
+        self.signatures.push(signature);
     }
 
     pub fn sign(&mut self, keypair: &Keypair) {
-        self.set_sign(
-            keypair.sign(self.hash().as_bytes()).to_bytes(),
-        );
+        self.set_sign(keypair.sign(&self.signing_bytes()).to_bytes());
+    }
+
+    pub fn cosign(&mut self, keypair: &Keypair) {
+        self.add_signature(keypair.sign(&self.signing_bytes()).to_bytes());
+    }
+}
+
+fn verify_signature(public_key: &PublicKeyBytes, message: &[u8], signature: &SignatureBytes) -> bool {
+    match PublicKey::from_bytes(public_key) {
+        Ok(public_key) => public_key.verify(message, &Signature::from(*signature)).is_ok(),
+        Err(_) => false,
     }
 }
 
 impl Hashable for Transaction {
     fn hash(&self) -> Hash {
         let mut hasher = Blake2s::new();
-
-        hasher.update(format!(
-            "{:?}",
-            (
-                self.nonce,
-                self.timestamp,
-                self.from.clone(),
-                self.data.clone()
-            )
-        ));
+        hasher.update(self.signing_bytes());
 
         hex::encode(hasher.finalize_fixed())
     }
-}
\ No newline at end of file
+}
+
+impl Encode for TransactionData {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            TransactionData::CreateAccount(account_id, public_key) => {
+                out.push(0);
+                account_id.encode(out);
+                public_key.encode(out);
+            }
+            TransactionData::CreateMultisigAccount {
+                account_id,
+                public_keys,
+                threshold,
+            } => {
+                out.push(4);
+                account_id.encode(out);
+                public_keys.encode(out);
+                threshold.encode(out);
+            }
+            TransactionData::MintInitialSupply { to, amount } => {
+                out.push(1);
+                to.encode(out);
+                amount.encode(out);
+            }
+            TransactionData::Transfer { to, amount, fee } => {
+                out.push(2);
+                to.encode(out);
+                amount.encode(out);
+                fee.encode(out);
+            }
+            TransactionData::Coinbase { to, amount } => {
+                out.push(3);
+                to.encode(out);
+                amount.encode(out);
+            }
+        }
+    }
+}
+
+impl Decode for TransactionData {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        match reader.read_tag()? {
+            0 => Ok(TransactionData::CreateAccount(
+                AccountId::decode(reader)?,
+                PublicKeyBytes::decode(reader)?,
+            )),
+            1 => Ok(TransactionData::MintInitialSupply {
+                to: AccountId::decode(reader)?,
+                amount: Balance::decode(reader)?,
+            }),
+            2 => Ok(TransactionData::Transfer {
+                to: AccountId::decode(reader)?,
+                amount: Balance::decode(reader)?,
+                fee: Balance::decode(reader)?,
+            }),
+            3 => Ok(TransactionData::Coinbase {
+                to: AccountId::decode(reader)?,
+                amount: Balance::decode(reader)?,
+            }),
+            4 => Ok(TransactionData::CreateMultisigAccount {
+                account_id: AccountId::decode(reader)?,
+                public_keys: Vec::<PublicKeyBytes>::decode(reader)?,
+                threshold: u32::decode(reader)?,
+            }),
+            tag => Err(invalid_tag(tag)),
+        }
+    }
+}
+
+impl Encode for Transaction {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.encode_unsigned(out);
+        // Tags 0 and 1 are the unsigned and single-signature encodings from
+        // before multisig, tag 2 carries two or more signatures.
+        match self.signatures.as_slice() {
+            [] => out.push(0),
+            [signature] => {
+                out.push(1);
+                signature.encode(out);
+            }
+            signatures => {
+                out.push(2);
+                signatures.to_vec().encode(out);
+            }
+        }
+    }
+}
+
+impl Decode for Transaction {
+    fn decode(reader: &mut Reader) -> Result<Self, Error> {
+        Ok(Self {
+            nonce: u128::decode(reader)?,
+            timestamp: Timestamp::decode(reader)?,
+            from: Option::<AccountId>::decode(reader)?,
+            data: TransactionData::decode(reader)?,
+            signatures: match reader.read_tag()? {
+                0 => vec![],
+                1 => vec![SignatureBytes::decode(reader)?],
+                2 => {
+                    let signatures = Vec::<SignatureBytes>::decode(reader)?;
+                    if signatures.len() < 2 {
+                        return Err(format!("Expected at least 2 signatures, got {}.", signatures.len()));
+                    }
+                    signatures
+                }
+                tag => return Err(invalid_tag(tag)),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer() -> Transaction {
+        Transaction {
+            nonce: 1,
+            timestamp: 1_600_000_000,
+            from: Some("alice".to_string()),
+            data: TransactionData::Transfer {
+                to: "bob".to_string(),
+                amount: 100,
+                fee: 2,
+            },
+            signatures: vec![],
+        }
+    }
+
+    #[test]
+    fn test_encoding_vector() {
+        let tx = transfer();
+
+        assert_eq!(
+            hex::encode(tx.to_bytes()),
+            "01".to_string()
+                + "00000000000000000000000000000001"
+                + "000000005f5e1000"
+                + "01" + "00000005" + "616c696365"
+                + "02" + "00000003" + "626f62"
+                + "00000000000000000000000000000064"
+                + "00000000000000000000000000000002"
+                + "00"
+        );
+        assert_eq!(
+            tx.hash(),
+            "44509e213b1b94a9782906065030cb120b423d34fca819ab9b98bd981a4b494d"
+        );
+    }
+
+    #[test]
+    fn test_signatures_encoding_vector() {
+        let unsigned = hex::encode(transfer().to_bytes());
+        let unsigned = &unsigned[..unsigned.len() - 2];
+
+        let mut tx = transfer();
+        tx.set_sign([9; 64]);
+        assert_eq!(hex::encode(tx.to_bytes()), unsigned.to_string() + "01" + &"09".repeat(64));
+
+        tx.add_signature([8; 64]);
+        let bytes = tx.to_bytes();
+        assert_eq!(
+            hex::encode(&bytes),
+            unsigned.to_string() + "02" + "00000002" + &"09".repeat(64) + &"08".repeat(64)
+        );
+        assert_eq!(Transaction::from_bytes(&bytes), Ok(tx));
+
+        let single = hex::decode(unsigned.to_string() + "02" + "00000001" + &"09".repeat(64)).unwrap();
+        assert_eq!(
+            Transaction::from_bytes(&single),
+            Err("Expected at least 2 signatures, got 1.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_encoding_roundtrip() {
+        let mut tx = transfer();
+        tx.set_sign([9; 64]);
+        assert_eq!(Transaction::from_bytes(&tx.to_bytes()), Ok(tx.clone()));
+
+        for data in [
+            TransactionData::CreateAccount("carol".to_string(), [3; 32]),
+            TransactionData::MintInitialSupply { to: "carol".to_string(), amount: 5 },
+            TransactionData::Coinbase { to: "carol".to_string(), amount: 7 },
+            TransactionData::CreateMultisigAccount {
+                account_id: "treasury".to_string(),
+                public_keys: vec![[1; 32], [2; 32]],
+                threshold: 2,
+            },
+        ] {
+            let tx = Transaction::new(data, None);
+            assert_eq!(Transaction::from_bytes(&tx.to_bytes()), Ok(tx));
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::traits::Hashable;
+use crate::types::{AccountId, Hash, PoolError, Transaction};
+
+#[derive(Default, Debug)]
+pub struct TransactionPool {
+    transactions: Vec<Transaction>,
+    hashes: HashSet<Hash>,
+    max_size: usize,
+    max_per_sender: usize,
+}
+
+impl TransactionPool {
+    pub fn new(max_size: usize, max_per_sender: usize) -> Self {
+        Self {
+            max_size,
+            max_per_sender,
+            ..Default::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    pub fn get(&self, hash: &Hash) -> Option<&Transaction> {
+        if !self.hashes.contains(hash) {
+            return None;
+        }
+        self.transactions.iter().find(|tx| &tx.hash() == hash)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Transaction> {
+        self.transactions.iter()
+    }
+
+    pub fn pending_from<'a>(
+        &'a self,
+        account_id: &'a AccountId,
+    ) -> impl Iterator<Item = &'a Transaction> {
+        self.transactions
+            .iter()
+            .filter(move |tx| tx.from.as_ref() == Some(account_id))
+    }
+
+    pub fn insert(&mut self, tx: Transaction) -> Result<(), PoolError> {
+        let hash = tx.hash();
+        if self.hashes.contains(&hash) {
+            return Err(PoolError::AlreadyInPool);
+        }
+
+        if let Some(from) = &tx.from {
+            if self.pending_from(from).count() >= self.max_per_sender {
+                return Err(PoolError::TooManyFromSender {
+                    account_id: from.clone(),
+                    max: self.max_per_sender,
+                });
+            }
+        }
+
+        if self.transactions.len() >= self.max_size {
+            match self.eviction_candidate(&tx) {
+                Some(index) if self.transactions[index].fee() < tx.fee() => {
+                    let evicted = self.transactions.remove(index);
+                    self.hashes.remove(&evicted.hash());
+                }
+                _ => return Err(PoolError::PoolFull),
+            }
+        }
+
+        let index = self
+            .transactions
+            .partition_point(|pending| pending.fee() >= tx.fee());
+        self.transactions.insert(index, tx);
+        self.hashes.insert(hash);
+        Ok(())
+    }
+
+    // Lowest fee transaction that is the last pending one of its sender, so
+    // evicting it never leaves a later nonce waiting on a missing one.
+    fn eviction_candidate(&self, tx: &Transaction) -> Option<usize> {
+        let mut last_nonces: HashMap<&AccountId, u128> = HashMap::new();
+        for pending in &self.transactions {
+            if let Some(from) = &pending.from {
+                let last_nonce = last_nonces.entry(from).or_insert(pending.nonce);
+                *last_nonce = (*last_nonce).max(pending.nonce);
+            }
+        }
+
+        self.transactions.iter().rposition(|pending| match &pending.from {
+            Some(from) => pending.from != tx.from && last_nonces[from] == pending.nonce,
+            None => true,
+        })
+    }
+
+    pub fn retain<F: FnMut(&Transaction) -> bool>(&mut self, mut f: F) {
+        let hashes = &mut self.hashes;
+        self.transactions.retain(|tx| {
+            let keep = f(tx);
+            if !keep {
+                hashes.remove(&tx.hash());
+            }
+            keep
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::create_transfer_tx;
+
+    use super::*;
+
+    #[test]
+    fn test_fee_order() {
+        let mut pool = TransactionPool::new(10, 10);
+        for (fee, nonce) in [(5, 0), (20, 1), (10, 2), (20, 3)] {
+            let tx = create_transfer_tx("alice".to_string(), "bob".to_string(), 1, fee, nonce);
+            assert!(pool.insert(tx).is_ok());
+        }
+
+        let pending: Vec<_> = pool.iter().map(|tx| (tx.fee(), tx.nonce)).collect();
+        assert_eq!(pending, vec![(20, 1), (20, 3), (10, 2), (5, 0)]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut pool = TransactionPool::new(3, 2);
+
+        let tx = create_transfer_tx("alice".to_string(), "bob".to_string(), 1, 5, 0);
+        assert!(pool.insert(tx.clone()).is_ok());
+        assert_eq!(pool.insert(tx).err().unwrap(), PoolError::AlreadyInPool);
+
+        let tx = create_transfer_tx("alice".to_string(), "bob".to_string(), 1, 5, 1);
+        assert!(pool.insert(tx).is_ok());
+        let tx = create_transfer_tx("alice".to_string(), "bob".to_string(), 1, 5, 2);
+        assert_eq!(
+            pool.insert(tx).err().unwrap(),
+            PoolError::TooManyFromSender {
+                account_id: "alice".to_string(),
+                max: 2,
+            }
+        );
+
+        let tx = create_transfer_tx("bob".to_string(), "alice".to_string(), 1, 3, 0);
+        assert!(pool.insert(tx).is_ok());
+        let tx = create_transfer_tx("carol".to_string(), "alice".to_string(), 1, 3, 0);
+        assert_eq!(pool.insert(tx).err().unwrap(), PoolError::PoolFull);
+
+        let tx = create_transfer_tx("carol".to_string(), "alice".to_string(), 1, 7, 0);
+        assert!(pool.insert(tx).is_ok());
+        assert_eq!(pool.len(), 3);
+        assert!(pool.iter().all(|tx| tx.from != Some("bob".to_string())));
+    }
+
+    #[test]
+    fn test_eviction_keeps_nonce_order() {
+        let mut pool = TransactionPool::new(3, 3);
+        for (from, fee, nonce) in [("alice", 1, 0), ("alice", 5, 1), ("bob", 3, 0)] {
+            let tx = create_transfer_tx(from.to_string(), "carol".to_string(), 1, fee, nonce);
+            assert!(pool.insert(tx).is_ok());
+        }
+
+        let tx = create_transfer_tx("carol".to_string(), "alice".to_string(), 1, 4, 0);
+        assert!(pool.insert(tx).is_ok());
+        let pending: Vec<_> = pool.iter().map(|tx| (tx.from.clone().unwrap(), tx.nonce)).collect();
+        assert_eq!(
+            pending,
+            vec![("alice".to_string(), 1), ("carol".to_string(), 0), ("alice".to_string(), 0)]
+        );
+
+        let tx = create_transfer_tx("dave".to_string(), "alice".to_string(), 1, 2, 0);
+        assert_eq!(pool.insert(tx).err().unwrap(), PoolError::PoolFull);
+
+        let tx = create_transfer_tx("alice".to_string(), "bob".to_string(), 1, 10, 2);
+        assert!(pool.insert(tx).is_ok());
+        assert!(pool.iter().all(|tx| tx.from == Some("alice".to_string())));
+
+        let tx = create_transfer_tx("dave".to_string(), "alice".to_string(), 1, 6, 0);
+        assert_eq!(pool.insert(tx).err().unwrap(), PoolError::PoolFull);
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+use crate::types::{AccountId, Balance};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxError {
+    AccountExists { account_id: AccountId },
+    MintOutsideGenesis,
+    InvalidAccount { account_id: AccountId },
+    InvalidBeneficiary { account_id: AccountId },
+    MissingSender,
+    SelfTransfer,
+    InvalidSender { account_id: AccountId },
+    InvalidReceiver { account_id: AccountId },
+    NonceUsed { expected: u128, got: u128 },
+    NonceGap { expected: u128, got: u128 },
+    AmountOverflow,
+    InsufficientBalance {
+        account_id: AccountId,
+        required: Balance,
+        available: Balance,
+    },
+    ImmatureBalance {
+        account_id: AccountId,
+        required: Balance,
+        available: Balance,
+    },
+    InvalidSignature,
+    InvalidThreshold { threshold: u32, keys: usize },
+    TooManyPublicKeys { count: usize, max: usize },
+    DuplicatePublicKey,
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::AccountExists { account_id } => {
+                write!(f, "AccountId already exist: {}", account_id)
+            }
+            TxError::MintOutsideGenesis => {
+                write!(f, "Initial supply can be minted only in genesis block.")
+            }
+            TxError::InvalidAccount { account_id } => write!(f, "Invalid account {}.", account_id),
+            TxError::InvalidBeneficiary { account_id } => {
+                write!(f, "Invalid beneficiary account {}.", account_id)
+            }
+            TxError::MissingSender => write!(f, "Invalid sender account id."),
+            TxError::SelfTransfer => write!(f, "Transfer to yourself."),
+            TxError::InvalidSender { account_id } => {
+                write!(f, "Invalid sender account {}.", account_id)
+            }
+            TxError::InvalidReceiver { account_id } => {
+                write!(f, "Invalid receiver account {}.", account_id)
+            }
+            TxError::NonceUsed { expected, got } => {
+                write!(f, "Nonce already used: expected {}, got {}.", expected, got)
+            }
+            TxError::NonceGap { expected, got } => {
+                write!(f, "Nonce gap: expected {}, got {}.", expected, got)
+            }
+            TxError::AmountOverflow => write!(f, "Amount overflow."),
+            TxError::InsufficientBalance {
+                account_id,
+                required,
+                available,
+            } => write!(
+                f,
+                "Account {} doesn't have enough currency: required {}, available {}.",
+                account_id, required, available
+            ),
+            TxError::ImmatureBalance {
+                account_id,
+                required,
+                available,
+            } => write!(
+                f,
+                "Account {} balance is not mature yet: required {}, available {}.",
+                account_id, required, available
+            ),
+            TxError::InvalidSignature => write!(f, "Signature invalid."),
+            TxError::InvalidThreshold { threshold, keys } => write!(
+                f,
+                "Invalid multisig threshold {} for {} public keys.",
+                threshold, keys
+            ),
+            TxError::TooManyPublicKeys { count, max } => write!(
+                f,
+                "Multisig account has too many public keys: {} of at most {}.",
+                count, max
+            ),
+            TxError::DuplicatePublicKey => write!(f, "Multisig account has duplicate public keys."),
+        }
+    }
+}
+
+impl std::error::Error for TxError {}
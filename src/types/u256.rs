@@ -0,0 +1,346 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Not, Shl, Shr};
+
+use crate::types::{Bits, Error};
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 4]);
+    pub const ONE: U256 = U256([1, 0, 0, 0]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    pub fn from_u64(value: u64) -> Self {
+        U256([value, 0, 0, 0])
+    }
+
+    pub fn from_u128(value: u128) -> Self {
+        U256([value as u64, (value >> 64) as u64, 0, 0])
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let start = 32 - (i + 1) * 8;
+            let mut chunk = [0; 8];
+            chunk.copy_from_slice(&bytes[start..start + 8]);
+            *limb = u64::from_be_bytes(chunk);
+        }
+        U256(limbs)
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            let start = 32 - (i + 1) * 8;
+            bytes[start..start + 8].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Self, Error> {
+        let decoded = hex::decode(hex).map_err(|error| error.to_string())?;
+        if decoded.len() > 32 {
+            return Err("Value doesn't fit in 256 bits.".to_string());
+        }
+        let mut bytes = [0; 32];
+        bytes[32 - decoded.len()..].copy_from_slice(&decoded);
+        Ok(Self::from_be_bytes(bytes))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|limb| *limb == 0)
+    }
+
+    pub fn low_u64(&self) -> u64 {
+        self.0[0]
+    }
+
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + 64 - self.0[i].leading_zeros();
+            }
+        }
+        0
+    }
+
+    fn bit(&self, index: u32) -> bool {
+        (self.0[(index / 64) as usize] >> (index % 64)) & 1 == 1
+    }
+
+    pub fn overflowing_add(self, rhs: Self) -> (Self, bool) {
+        let mut result = [0; 4];
+        let mut carry = false;
+        for (i, limb) in result.iter_mut().enumerate() {
+            let (sum, overflow_a) = self.0[i].overflowing_add(rhs.0[i]);
+            let (sum, overflow_b) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = overflow_a || overflow_b;
+        }
+        (U256(result), carry)
+    }
+
+    pub fn overflowing_sub(self, rhs: Self) -> (Self, bool) {
+        let mut result = [0; 4];
+        let mut borrow = false;
+        for (i, limb) in result.iter_mut().enumerate() {
+            let (diff, overflow_a) = self.0[i].overflowing_sub(rhs.0[i]);
+            let (diff, overflow_b) = diff.overflowing_sub(borrow as u64);
+            *limb = diff;
+            borrow = overflow_a || overflow_b;
+        }
+        (U256(result), borrow)
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        match self.overflowing_add(rhs) {
+            (result, false) => Some(result),
+            _ => None,
+        }
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        match self.overflowing_sub(rhs) {
+            (result, false) => Some(result),
+            _ => None,
+        }
+    }
+
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        self.checked_add(rhs).unwrap_or(U256::MAX)
+    }
+
+    pub fn checked_mul_u64(self, rhs: u64) -> Option<Self> {
+        let mut result = [0; 4];
+        let mut carry = 0u128;
+        for (i, limb) in result.iter_mut().enumerate() {
+            let product = self.0[i] as u128 * rhs as u128 + carry;
+            *limb = product as u64;
+            carry = product >> 64;
+        }
+        match carry {
+            0 => Some(U256(result)),
+            _ => None,
+        }
+    }
+
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        if rhs.is_zero() {
+            return None;
+        }
+
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for i in (0..self.bits()).rev() {
+            remainder = remainder << 1;
+            if self.bit(i) {
+                remainder.0[0] |= 1;
+            }
+            if remainder >= rhs {
+                remainder = remainder.overflowing_sub(rhs).0;
+                quotient.0[(i / 64) as usize] |= 1 << (i % 64);
+            }
+        }
+        Some(quotient)
+    }
+
+    // Bitcoin's compact format: a size byte and a 3-byte mantissa whose top
+    // bit is a sign, so negative values are rejected like overflowing ones.
+    pub fn from_compact(bits: Bits) -> Option<Self> {
+        if bits < 0 || bits & 0x00800000 != 0 {
+            return None;
+        }
+        let size = (bits >> 24) as u32;
+        let mantissa = (bits & 0x7fffff) as u64;
+
+        if size <= 3 {
+            return Some(U256::from_u64(mantissa >> (8 * (3 - size))));
+        }
+        if mantissa != 0 && 8 * (size - 3) + (64 - mantissa.leading_zeros()) > 256 {
+            return None;
+        }
+        Some(U256::from_u64(mantissa) << (8 * (size - 3)))
+    }
+
+    pub fn to_compact(&self) -> Bits {
+        let mut size = self.bits().div_ceil(8);
+        let mut mantissa = if size <= 3 {
+            self.low_u64() << (8 * (3 - size))
+        } else {
+            (*self >> (8 * (size - 3))).low_u64()
+        };
+        if mantissa & 0x00800000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+        ((size << 24) as u64 | mantissa) as Bits
+    }
+
+    // 2^256 / (target + 1), computed as !target / (target + 1) + 1 so the
+    // numerator fits in 256 bits.
+    pub fn work(&self) -> Self {
+        match self.checked_add(U256::ONE) {
+            Some(divisor) => (!*self).checked_div(divisor).unwrap().saturating_add(U256::ONE),
+            None => U256::ONE,
+        }
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        self.0
+            .iter()
+            .rev()
+            .fold(0., |value, limb| value * 18_446_744_073_709_551_616. + *limb as f64)
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Not for U256 {
+    type Output = U256;
+
+    fn not(self) -> Self::Output {
+        U256([!self.0[0], !self.0[1], !self.0[2], !self.0[3]])
+    }
+}
+
+impl Shl<u32> for U256 {
+    type Output = U256;
+
+    fn shl(self, shift: u32) -> Self::Output {
+        let mut result = [0; 4];
+        let limbs = (shift / 64) as usize;
+        let bits = shift % 64;
+        for (i, limb) in result.iter_mut().enumerate().skip(limbs) {
+            *limb = self.0[i - limbs] << bits;
+            if bits > 0 && i > limbs {
+                *limb |= self.0[i - limbs - 1] >> (64 - bits);
+            }
+        }
+        U256(result)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = U256;
+
+    fn shr(self, shift: u32) -> Self::Output {
+        let mut result = [0; 4];
+        let limbs = (shift / 64) as usize;
+        let bits = shift % 64;
+        for (i, limb) in result.iter_mut().enumerate().take(4usize.saturating_sub(limbs)) {
+            *limb = self.0[i + limbs] >> bits;
+            if bits > 0 && i + limbs + 1 < 4 {
+                *limb |= self.0[i + limbs + 1] << (64 - bits);
+            }
+        }
+        U256(result)
+    }
+}
+
+impl fmt::LowerHex for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.to_be_bytes()))
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:x}", self)
+    }
+}
+
+impl fmt::Debug for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "U256({:x})", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact() {
+        assert_eq!(
+            U256::from_compact(0x1d00ffff).unwrap(),
+            U256::from_hex("00000000ffff0000000000000000000000000000000000000000000000000000").unwrap()
+        );
+        assert_eq!(U256::from_compact(0x03123456), Some(U256::from_u64(0x123456)));
+        assert_eq!(U256::from_compact(0x02123456), Some(U256::from_u64(0x1234)));
+        assert_eq!(U256::from_compact(0x01003456), Some(U256::ZERO));
+        assert_eq!(U256::from_compact(0x04123456), Some(U256::from_u64(0x12345600)));
+        assert_eq!(U256::from_compact(0x207fffff), Some(U256::from_u64(0x7fffff) << 232));
+        assert_eq!(U256::from_compact(0x21010000), None);
+        assert_eq!(U256::from_compact(0x22000100), None);
+        assert_eq!(U256::from_compact(-1), None);
+        assert_eq!(U256::from_compact(0x04923456), None);
+        assert_eq!(U256::from_compact(0x1effffff), None);
+        assert_eq!(U256::from_compact(0x01800000), None);
+
+        for bits in [0x1d00ffff, 0x1f00ffff, 0x03123456, 0x04123456, 0x207fffff, 0x1b0404cb, 0x02008000] {
+            assert_eq!(U256::from_compact(bits).unwrap().to_compact(), bits);
+        }
+        assert_eq!(U256::from_u64(0x1234).to_compact(), 0x02123400);
+        assert_eq!(U256::ZERO.to_compact(), 0);
+        assert_eq!(U256::from_u64(0x123456789).to_compact(), 0x05012345);
+        assert_eq!(U256::from_u64(0x80).to_compact(), 0x02008000);
+        assert_eq!(U256::from_u64(0xffffff).to_compact(), 0x0400ffff);
+        assert_eq!((U256::from_u64(0xffffff) << 216).to_compact(), 0x1f00ffff);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = U256::from_u128(u128::MAX);
+        assert_eq!(a.checked_add(U256::ONE), Some(U256::ONE << 128));
+        assert_eq!((U256::ONE << 128).checked_sub(U256::ONE), Some(a));
+        assert_eq!(U256::ZERO.checked_sub(U256::ONE), None);
+        assert_eq!(U256::MAX.checked_add(U256::ONE), None);
+        assert_eq!(U256::MAX.saturating_add(U256::ONE), U256::MAX);
+        assert_eq!(a.checked_mul_u64(2), Some((U256::ONE << 129).checked_sub(U256::from_u64(2)).unwrap()));
+        assert_eq!(U256::MAX.checked_mul_u64(2), None);
+        assert_eq!((U256::ONE << 200).checked_div(U256::ONE << 100), Some(U256::ONE << 100));
+        assert_eq!(U256::from_u64(100).checked_div(U256::from_u64(7)), Some(U256::from_u64(14)));
+        assert_eq!(U256::ONE.checked_div(U256::ZERO), None);
+        assert_eq!((U256::ONE << 255) >> 255, U256::ONE);
+        assert_eq!((U256::from_u64(0xff) << 60) >> 64, U256::from_u64(0xf));
+        assert!(U256::ONE << 64 > U256::from_u64(u64::MAX));
+        assert_eq!(U256::MAX.bits(), 256);
+        assert_eq!(U256::ZERO.bits(), 0);
+        assert_eq!((U256::ONE << 64).as_f64(), 18_446_744_073_709_551_616.);
+    }
+
+    #[test]
+    fn test_hex() {
+        let hash = "00000000000000000007c0a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5";
+        let value = U256::from_hex(hash).unwrap();
+        assert_eq!(format!("{:x}", value), hash);
+        assert_eq!(U256::from_be_bytes(value.to_be_bytes()), value);
+        assert_eq!(U256::from_hex("ff"), Ok(U256::from_u64(0xff)));
+        assert!(U256::from_hex(&"00".repeat(33)).is_err());
+        assert!(U256::from_hex("zz").is_err());
+    }
+
+    #[test]
+    fn test_work() {
+        assert_eq!(U256::MAX.work(), U256::ONE);
+        assert_eq!(U256::ZERO.work(), U256::MAX);
+        assert_eq!((U256::ONE << 255).checked_sub(U256::ONE).unwrap().work(), U256::from_u64(2));
+        assert_eq!(
+            U256::from_compact(0x1d00ffff).unwrap().work(),
+            U256::from_u64(0x0100010001)
+        );
+    }
+}
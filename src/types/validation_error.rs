@@ -0,0 +1,48 @@
+use std::fmt;
+
+use crate::types::{AccountId, BlockError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    InvalidHash { height: usize },
+    MissingPrevHash { height: usize },
+    GenesisPrevHash,
+    BrokenLink { height: usize },
+    InvalidBlock { height: usize, error: BlockError },
+    AccountMismatch { account_id: AccountId },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::InvalidHash { height } => {
+                write!(f, "Block {} has invalid hash.", height)
+            }
+            ValidationError::MissingPrevHash { height } => {
+                write!(f, "Block {} doesn't have prev_hash.", height)
+            }
+            ValidationError::GenesisPrevHash => write!(f, "Genesis block shouldn't have prev_hash."),
+            ValidationError::BrokenLink { height } => write!(
+                f,
+                "Block {} prev_hash doesn't match Block {} hash.",
+                height,
+                height - 1
+            ),
+            ValidationError::InvalidBlock { height, error } => {
+                write!(f, "Block {} is invalid: {}", height, error)
+            }
+            ValidationError::AccountMismatch { account_id } => {
+                write!(f, "Account {} doesn't match replayed state.", account_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ValidationError::InvalidBlock { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
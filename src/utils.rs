@@ -1,41 +1,11 @@
-use std::ops::Div;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use blake2::{Blake2s, Digest};
 use ed25519_dalek::Keypair;
 use rand::Rng;
 
-use crate::types::{AccountId, Balance, Bits, Block, Blockchain, Error, Hash, Timestamp, Transaction, TransactionData};
-
-pub fn get_bits_from_hash(hash: Hash) -> Bits {
-    let mut a = 0;
-    for (i, char) in hash.clone().chars().enumerate() {
-        if char != '0' {
-            a = i;
-            break;
-        }
-    }
-
-    let mut hash = hash[a..hash.len()].to_string().clone();
-
-    while hash[..6].ends_with("0") {
-        hash.insert(0, '0');
-    }
-
-    if hash.len() % 2 != 0 {
-        hash.push('0');
-    }
-
-    let exponent = format!("{:x}", hash.len().div(2));
-    let coefficient = &hash[..6].to_string().clone();
-
-    let bits = Bits::from_str_radix(
-        &(exponent.to_string() + coefficient.to_string().as_ref()),
-        16,
-    )
-        .unwrap();
-    bits
-}
+use crate::hd::{account_path, ExtendedKey};
+use crate::types::{AccountId, Balance, Block, BlockError, Blockchain, ChainUpdate, Error, Timestamp, Transaction, TransactionData};
 
 pub fn generate_account_id() -> AccountId {
     let mut rng = rand::thread_rng();
@@ -46,14 +16,44 @@ pub fn generate_account_id() -> AccountId {
 
 pub fn create_account_tx(account_id: AccountId) -> (Keypair, Transaction) {
     let keypair_account = Keypair::generate(&mut rand::rngs::OsRng {});
+    let tx_create_account = create_account_tx_with_keypair(account_id, &keypair_account);
+    (keypair_account, tx_create_account)
+}
+
+pub fn create_derived_account_tx(
+    account_id: AccountId,
+    seed: &[u8],
+    index: u32,
+) -> Result<(Keypair, Transaction), Error> {
+    let keypair_account = ExtendedKey::derive_path(seed, &account_path(index))?.keypair();
+    let tx_create_account = create_account_tx_with_keypair(account_id, &keypair_account);
+    Ok((keypair_account, tx_create_account))
+}
+
+pub fn create_account_tx_with_keypair(account_id: AccountId, keypair: &Keypair) -> Transaction {
+    Transaction::new(
+        TransactionData::CreateAccount(account_id, *keypair.public.as_bytes()),
+        None,
+    )
+}
+
+pub fn create_multisig_account_tx(
+    account_id: AccountId,
+    threshold: u32,
+    key_count: usize,
+) -> (Vec<Keypair>, Transaction) {
+    let keypairs: Vec<Keypair> = (0..key_count)
+        .map(|_| Keypair::generate(&mut rand::rngs::OsRng {}))
+        .collect();
     let tx_create_account = Transaction::new(
-        TransactionData::CreateAccount(
-            account_id.to_string(),
-            keypair_account.public.as_bytes().clone(),
-        ),
+        TransactionData::CreateMultisigAccount {
+            account_id,
+            public_keys: keypairs.iter().map(|keypair| *keypair.public.as_bytes()).collect(),
+            threshold,
+        },
         None,
     );
-    (keypair_account, tx_create_account)
+    (keypairs, tx_create_account)
 }
 
 pub fn mint_initial_supply(account_id: AccountId, amount: Balance) -> Transaction {
@@ -66,18 +66,29 @@ pub fn mint_initial_supply(account_id: AccountId, amount: Balance) -> Transactio
     )
 }
 
-pub fn append_block(bc: &mut Blockchain, nonce: u128) -> Block {
-    let mut block = Block::new(bc.get_last_block_hash());
+pub fn create_coinbase_tx(account_id: AccountId, amount: Balance) -> Transaction {
+    Transaction::new(
+        TransactionData::Coinbase {
+            to: account_id.to_string(),
+            amount,
+        },
+        None,
+    )
+}
+
+pub fn append_block(bc: &mut Blockchain) -> Block {
+    let mut block = new_block(bc);
     let keypair_account = Keypair::generate(&mut rand::rngs::OsRng {});
     let tx_create_account = Transaction::new(
         TransactionData::CreateAccount(
             generate_account_id(),
-            keypair_account.public.as_bytes().clone(),
+            *keypair_account.public.as_bytes(),
         ),
         None,
     );
-    block.set_nonce(nonce);
     block.add_transaction(tx_create_account);
+    block.set_state_root(bc.state_root_after(&block).unwrap());
+    block.mine(bc.target).unwrap();
     let block_clone = block.clone();
 
     assert!(bc.append_block(block).is_ok());
@@ -85,27 +96,46 @@ pub fn append_block(bc: &mut Blockchain, nonce: u128) -> Block {
     block_clone
 }
 
-pub fn create_transfer_tx(from: AccountId, to: AccountId, amount: Balance) -> Transaction {
-    Transaction::new(
+// Empty block on the active tip, timestamped after the median time past.
+pub fn new_block(bc: &Blockchain) -> Block {
+    let mut block = Block::new(bc.get_last_block_hash());
+    block.set_timestamp(block.timestamp().max(bc.median_time_past() + 1));
+    block
+}
+
+pub fn create_transfer_tx(
+    from: AccountId,
+    to: AccountId,
+    amount: Balance,
+    fee: Balance,
+    nonce: u128,
+) -> Transaction {
+    let mut tx = Transaction::new(
         TransactionData::Transfer {
             to: to.to_string(),
             amount,
+            fee,
         },
         Some(from.to_string()),
-    )
+    );
+    tx.set_nonce(nonce);
+    tx
 }
 
 pub fn append_block_with_tx(
     bc: &mut Blockchain,
-    nonce: u128,
     transactions: Vec<Transaction>,
-) -> Result<(), Error> {
-    let mut block = Block::new(bc.get_last_block_hash());
-    block.set_nonce(nonce);
+) -> Result<ChainUpdate, BlockError> {
+    let mut block = new_block(bc);
 
     for tx in transactions {
         block.add_transaction(tx);
     }
+    // Invalid blocks are left without a state root, append_block reports why.
+    if let Ok(state_root) = bc.state_root_after(&block) {
+        block.set_state_root(state_root);
+    }
+    block.mine(bc.target)?;
 
     bc.append_block(block)
 }
@@ -124,4 +154,24 @@ mod tests {
     fn test_generate() {
         dbg!(generate_account_id());
     }
+
+    #[test]
+    fn test_derived_account() {
+        let seed = [7; 32];
+        let (keypair, tx) = create_derived_account_tx("alice".to_string(), &seed, 0).unwrap();
+        let (same, _) = create_derived_account_tx("alice".to_string(), &seed, 0).unwrap();
+        let (other, _) = create_derived_account_tx("alice".to_string(), &seed, 1).unwrap();
+
+        assert_eq!(keypair.to_bytes(), same.to_bytes());
+        assert_ne!(keypair.public, other.public);
+        assert_eq!(
+            tx.data,
+            TransactionData::CreateAccount("alice".to_string(), *keypair.public.as_bytes())
+        );
+        assert_eq!(
+            keypair.public.to_bytes(),
+            ExtendedKey::derive_path(&seed, "m/44'/1'/0'").unwrap().public_key()
+        );
+        assert!(create_derived_account_tx("alice".to_string(), &seed, 1 << 31).is_err());
+    }
 }
@@ -0,0 +1,152 @@
+use std::io::Read;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use blockchain_workshop::network::{Node, MAX_PEERS};
+use blockchain_workshop::traits::Hashable;
+use blockchain_workshop::types::{Block, Blockchain, ChainUpdate};
+use blockchain_workshop::utils::{
+    create_account_tx, create_transfer_tx, generate_account_id, mint_initial_supply, new_block,
+};
+
+fn start_node(chain: Blockchain) -> Node {
+    Node::start(Arc::new(Mutex::new(chain)), "127.0.0.1:0".parse().unwrap()).unwrap()
+}
+
+fn mine_block(node: &Node) -> Block {
+    let mut chain = node.chain().lock().unwrap();
+    let mut block = new_block(&chain);
+    block.add_transaction(create_account_tx(generate_account_id()).1);
+    block.set_state_root(chain.state_root_after(&block).unwrap());
+    block.mine(chain.target()).unwrap();
+    block
+}
+
+fn tip(node: &Node) -> Option<String> {
+    node.chain().lock().unwrap().get_last_block_hash()
+}
+
+fn wait_until(condition: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while !condition() {
+        assert!(Instant::now() < deadline, "timed out waiting for the network");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_initial_sync() {
+    let miner = start_node(Blockchain::new());
+    for _ in 0..5 {
+        let block = mine_block(&miner);
+        assert_eq!(miner.submit_block(block), Ok(ChainUpdate::Extended));
+    }
+
+    let fresh = start_node(Blockchain::new());
+    fresh.connect(miner.local_addr()).unwrap();
+    wait_until(|| tip(&fresh) == tip(&miner));
+    assert_eq!(fresh.chain().lock().unwrap().len(), 5);
+    assert_eq!(fresh.peers().len(), 1);
+
+    fresh.stop();
+    miner.stop();
+}
+
+#[test]
+fn test_block_gossip() {
+    let a = start_node(Blockchain::new());
+    let b = start_node(Blockchain::new());
+    let c = start_node(Blockchain::new());
+    b.connect(a.local_addr()).unwrap();
+    c.connect(b.local_addr()).unwrap();
+    wait_until(|| a.peers().len() == 1 && b.peers().len() == 2 && c.peers().len() == 1);
+
+    for _ in 0..3 {
+        let block = mine_block(&a);
+        let hash = block.hash();
+        a.submit_block(block).unwrap();
+        wait_until(|| tip(&c) == Some(hash.clone()));
+    }
+
+    // A block mined at the far end travels back through the relay.
+    let block = mine_block(&c);
+    let hash = block.hash();
+    c.submit_block(block).unwrap();
+    wait_until(|| tip(&a) == Some(hash.clone()) && tip(&b) == Some(hash.clone()));
+    assert_eq!(a.chain().lock().unwrap().len(), 4);
+
+    for node in [c, b, a] {
+        node.stop();
+    }
+}
+
+#[test]
+fn test_transaction_gossip() {
+    let mut genesis = Blockchain::new();
+    let (keypair, tx_create_satoshi) = create_account_tx("satoshi".to_string());
+    let (_, tx_create_alice) = create_account_tx("alice".to_string());
+    let mut block = Block::new(None);
+    block.add_transaction(tx_create_satoshi);
+    block.add_transaction(mint_initial_supply("satoshi".to_string(), 1_000));
+    block.add_transaction(tx_create_alice);
+    block.set_state_root(genesis.state_root_after(&block).unwrap());
+    block.mine(genesis.target()).unwrap();
+    genesis.append_block(block.clone()).unwrap();
+
+    let a = start_node(genesis);
+    let b = start_node(Blockchain::new());
+    let c = start_node(Blockchain::new());
+    b.connect(a.local_addr()).unwrap();
+    c.connect(b.local_addr()).unwrap();
+    wait_until(|| tip(&c) == Some(block.hash()));
+
+    let mut tx = create_transfer_tx("satoshi".to_string(), "alice".to_string(), 10, 1, 0);
+    tx.sign(&keypair);
+    c.submit_transaction(tx.clone()).unwrap();
+    wait_until(|| a.chain().lock().unwrap().transaction_pool().contains(&tx.hash()));
+    assert!(b.chain().lock().unwrap().transaction_pool().contains(&tx.hash()));
+
+    for node in [c, b, a] {
+        node.stop();
+    }
+}
+
+#[test]
+fn test_genesis_mismatch() {
+    let a = start_node(Blockchain::new());
+    let b = start_node(Blockchain::new());
+    let block = mine_block(&a);
+    a.submit_block(block).unwrap();
+    let block = mine_block(&b);
+    b.submit_block(block).unwrap();
+
+    b.connect(a.local_addr()).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert!(a.peers().is_empty());
+    assert!(b.peers().is_empty());
+    assert_ne!(tip(&a), tip(&b));
+
+    a.stop();
+    b.stop();
+}
+
+#[test]
+fn test_peer_limit() {
+    let node = start_node(Blockchain::new());
+    let mut streams = vec![];
+    for _ in 0..MAX_PEERS {
+        let mut stream = TcpStream::connect(node.local_addr()).unwrap();
+        // Every accepted peer is greeted with a version message.
+        let mut len = [0; 4];
+        stream.read_exact(&mut len).unwrap();
+        streams.push(stream);
+    }
+
+    let mut rejected = TcpStream::connect(node.local_addr()).unwrap();
+    let mut buffer = vec![];
+    assert_eq!(rejected.read_to_end(&mut buffer).unwrap_or(0), 0);
+
+    node.stop();
+}